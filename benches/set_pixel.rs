@@ -0,0 +1,144 @@
+//! Measures how long `Graphics2D::flush` takes for the `set_pixel`
+//! update patterns used in `examples/ex1.rs`
+//!
+//! Run with `cargo bench --bench set_pixel` (needs a GPU adapter)
+//!
+//! Each flush used to map and rewrite every sprite between the lowest
+//! and the highest changed index. It now uploads only the changed
+//! ranges, merging ranges less than 17 sprites apart. Data uploaded per
+//! flush with 100-byte instances:
+//!
+//! | pattern                    | before     | after                    |
+//! |----------------------------|------------|--------------------------|
+//! | full screen                | 46875 KiB  | 46875 KiB in 1 copy      |
+//! | 1000 consecutive pixels    | 97.7 KiB   | 97.7 KiB in 1 copy       |
+//! | 1000 scattered pixels      | 46779 KiB  | 124 KiB in ~965 copies   |
+//! | 1 pixel changed 1000 times | 0.1 KiB    | 0.1 KiB in 1 copy        |
+//!
+//! Every pattern is also timed against a baseline that uploads what
+//! the old flush did: before the pattern's own changes, every pixel
+//! between the lowest and the highest one changed is set as well, so
+//! that the whole range is uploaded in one copy. The timings depend
+//! on the adapter, so they are printed side by side rather than
+//! recorded here.
+use a2d::Graphics2D;
+use futures::executor::block_on;
+use std::time::Duration;
+use std::time::Instant;
+
+const WIDTH: u32 = 800;
+const HEIGHT: u32 = 600;
+const ITERATIONS: u32 = 100;
+
+/// A pixel index (`y * WIDTH + x`) and the color it's set to
+type Change = (usize, [f32; 3]);
+
+fn main() {
+    let mut graphics = block_on(Graphics2D::new_offscreen(WIDTH, HEIGHT)).unwrap();
+
+    // Create the pixel batch up front so that it isn't part of any measurement
+    graphics.set_pixel(0, 0, [0.0, 0.0, 0.0]).unwrap();
+    graphics.flush().unwrap();
+
+    bench("full screen", &mut graphics, |_| {
+        (0..(WIDTH * HEIGHT) as usize)
+            .map(|pos| (pos, [1.0, 0.5, 0.0]))
+            .collect()
+    });
+
+    // What ex1 does every frame: 1000 consecutive pixels
+    let mut pos = 0;
+    bench("1000 consecutive pixels", &mut graphics, |_| {
+        (0..1000)
+            .map(|_| {
+                pos = (pos + 1) % (WIDTH * HEIGHT) as usize;
+                (pos, [0.0, 0.5, 1.0])
+            })
+            .collect()
+    });
+
+    // Pixels far apart from each other, which previously
+    // uploaded everything in between
+    let mut seed: u32 = 12345;
+    bench("1000 scattered pixels", &mut graphics, |_| {
+        (0..1000)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                (
+                    (seed >> 8) as usize % (WIDTH * HEIGHT) as usize,
+                    [0.0, 1.0, 0.0],
+                )
+            })
+            .collect()
+    });
+
+    // The same pixel changed many times, which previously
+    // applied every change in order
+    bench("1 pixel changed 1000 times", &mut graphics, |i| {
+        (0..1000)
+            .map(|j| {
+                let c = ((i * 1000 + j) % 256) as f32 / 255.0;
+                (10 * WIDTH as usize + 10, [c, c, c])
+            })
+            .collect()
+    });
+}
+
+/// Times the changes made by `pattern` on every iteration, then
+/// the same changes with the baseline's extra ones
+fn bench<F>(name: &str, graphics: &mut Graphics2D, pattern: F)
+where
+    F: FnMut(u32) -> Vec<Change>,
+{
+    let changes: Vec<_> = (0..ITERATIONS).map(pattern).collect();
+    let (update_time, flush_time) = measure(graphics, &changes, false);
+    let (_, baseline_flush_time) = measure(graphics, &changes, true);
+    println!(
+        "{:<28} update {:>9.3} ms/iter, flush + read back {:>9.3} ms/iter (baseline {:>9.3} ms/iter)",
+        name,
+        per_iter(update_time),
+        per_iter(flush_time),
+        per_iter(baseline_flush_time),
+    );
+}
+
+/// Returns the total time spent setting pixels and flushing
+fn measure(
+    graphics: &mut Graphics2D,
+    changes: &[Vec<Change>],
+    baseline: bool,
+) -> (Duration, Duration) {
+    let mut update_time = Duration::default();
+    let mut flush_time = Duration::default();
+    for changes in changes {
+        let start = Instant::now();
+        if baseline {
+            let min = changes.iter().map(|&(pos, _)| pos).min().unwrap_or(0);
+            let max = changes.iter().map(|&(pos, _)| pos).max().unwrap_or(0);
+            for pos in min..=max {
+                set_pixel(graphics, pos, [0.0, 0.0, 0.0]);
+            }
+        }
+        for &(pos, color) in changes {
+            set_pixel(graphics, pos, color);
+        }
+        let updated = Instant::now();
+        graphics.flush().unwrap();
+        // Reading back the target waits for the GPU to finish the upload.
+        // Nothing is rendered, so this adds the same fixed cost to every
+        // pattern. With the software renderer, this only copies the last frame
+        graphics.read_pixels().unwrap();
+        flush_time += updated.elapsed();
+        update_time += updated - start;
+    }
+    (update_time, flush_time)
+}
+
+fn set_pixel(graphics: &mut Graphics2D, pos: usize, color: [f32; 3]) {
+    let (x, y) = (pos % WIDTH as usize, pos / WIDTH as usize);
+    graphics.set_pixel(x, y, color).unwrap();
+}
+
+fn per_iter(time: Duration) -> f64 {
+    time.as_secs_f64() * 1000.0 / ITERATIONS as f64
+}
@@ -0,0 +1,30 @@
+//! Regenerates the checked in SPIR-V for the builtin shaders
+//!
+//! Run with `cargo run --example compile_shaders --features glsl`
+use a2d::compile_glsl;
+use a2d::ShaderStage;
+use std::fs;
+use std::path::Path;
+
+fn main() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("src")
+        .join("shaders");
+    for &(name, stage) in &[
+        ("shader.vert", ShaderStage::Vertex),
+        ("shader.frag", ShaderStage::Fragment),
+        ("post.vert", ShaderStage::Vertex),
+        ("blur.frag", ShaderStage::Fragment),
+        ("bright.frag", ShaderStage::Fragment),
+        ("bloom.frag", ShaderStage::Fragment),
+        ("vignette.frag", ShaderStage::Fragment),
+        ("lut.frag", ShaderStage::Fragment),
+        ("crt.frag", ShaderStage::Fragment),
+    ] {
+        let source = fs::read_to_string(dir.join(name)).unwrap();
+        let spirv = compile_glsl(&source, stage).unwrap();
+        let out_path = dir.join(format!("{}.spv", name));
+        fs::write(&out_path, spirv).unwrap();
+        println!("Wrote {}", out_path.display());
+    }
+}
@@ -37,6 +37,17 @@ fn main() {
     }
 
     graphics.draw_text(0, 0, "HeLlo WwOoRrLlDd!D").unwrap();
+    graphics
+        .draw_text_styled(1, 0, "Styled text", [1.0, 1.0, 0.0], [0.0, 0.0, 0.5])
+        .unwrap();
+    graphics
+        .draw_text_at(
+            [300.0, 400.0],
+            "Free positioned text",
+            32.0,
+            [0.5, 1.0, 0.5],
+        )
+        .unwrap();
     graphics.flush().unwrap();
 
     event_loop.run(move |event, _, control_flow| {
@@ -61,19 +72,17 @@ fn main() {
                     graphics.flush().unwrap();
                     window.request_redraw();
                 }
-                WindowEvent::KeyboardInput { input, .. } => match input {
-                    KeyboardInput {
-                        state: ElementState::Pressed,
-                        virtual_keycode: Some(code),
-                        ..
-                    } => match code {
-                        VirtualKeyCode::Escape => {
-                            *control_flow = ControlFlow::Exit;
-                        }
-                        _ => {}
-                    },
-                    _ => {}
-                },
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            state: ElementState::Pressed,
+                            virtual_keycode: Some(VirtualKeyCode::Escape),
+                            ..
+                        },
+                    ..
+                } => {
+                    *control_flow = ControlFlow::Exit;
+                }
                 WindowEvent::Resized(physical_size) => {
                     // TODO: update size
                     let logical_size =
@@ -0,0 +1,112 @@
+use a2d::Batch;
+use a2d::Graphics2D;
+use a2d::Sheet;
+use a2d::SpriteDesc;
+use futures::executor::block_on;
+use winit::{
+    dpi::LogicalSize,
+    event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent},
+    event_loop::{ControlFlow, EventLoop},
+    window::WindowBuilder,
+};
+
+fn main() {
+    let width = 800;
+    let height = 600;
+    let event_loop = EventLoop::new();
+    let window = WindowBuilder::new()
+        .with_inner_size(LogicalSize { width, height })
+        .build(&event_loop)
+        .unwrap();
+
+    let mut graphics = block_on(Graphics2D::new(width, height, &window)).unwrap();
+
+    // A 2x2 sheet with a different color in each cell
+    let sheet = Sheet::from_colors(
+        &mut graphics,
+        2,
+        2,
+        vec![
+            [1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [0.0, 0.0, 1.0],
+            [1.0, 1.0, 0.0],
+        ],
+    )
+    .unwrap();
+
+    let mut descs = vec![];
+    for i in 0..4 {
+        let x = 100.0 + 150.0 * i as f32;
+        descs.push(SpriteDesc::new(i, [x, 250.0, x + 100.0, 350.0]));
+    }
+    let batch = Batch::new(&mut graphics, sheet, 2, 2, &descs);
+    graphics.set_batch(0, batch).unwrap();
+    graphics.flush().unwrap();
+
+    let mut theta = 0.0;
+
+    event_loop.run(move |event, _, control_flow| {
+        *control_flow = ControlFlow::Wait;
+        match event {
+            Event::RedrawRequested(_) => {
+                graphics.force_render().unwrap();
+            }
+            Event::MainEventsCleared => {
+                theta += 0.02;
+                let batch = graphics.batch_mut(0).unwrap();
+                for i in 0..batch.len() {
+                    batch.get(i).rotate(theta * (i + 1) as f32);
+                }
+                graphics.flush().unwrap();
+                window.request_redraw();
+                std::thread::sleep(std::time::Duration::from_secs_f64(1.0 / 60.0));
+            }
+            Event::WindowEvent {
+                ref event,
+                window_id: _,
+            } => match event {
+                WindowEvent::CloseRequested => {
+                    *control_flow = ControlFlow::Exit;
+                }
+                WindowEvent::KeyboardInput { input, .. } => match input {
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::Escape),
+                        ..
+                    } => {
+                        *control_flow = ControlFlow::Exit;
+                    }
+                    // Space adds a sprite, Backspace removes the first one
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::Space),
+                        ..
+                    } => {
+                        let batch = graphics.batch_mut(0).unwrap();
+                        let i = batch.len();
+                        let x = 20.0 + 60.0 * (i % 13) as f32;
+                        let y = 20.0 + 60.0 * (i / 13 % 9) as f32;
+                        batch.push(&SpriteDesc::new(i % 4, [x, y, x + 50.0, y + 50.0]));
+                    }
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::Back),
+                        ..
+                    } => {
+                        let batch = graphics.batch_mut(0).unwrap();
+                        if !batch.is_empty() {
+                            batch.swap_remove(0);
+                        }
+                    }
+                    _ => {}
+                },
+                WindowEvent::Resized(physical_size) => {
+                    graphics.resized(physical_size.width, physical_size.height);
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+    });
+}
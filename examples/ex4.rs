@@ -0,0 +1,78 @@
+use a2d::Graphics2D;
+use a2d::Terminal;
+use futures::executor::block_on;
+use std::io::Read;
+use std::process::Command;
+use std::process::Stdio;
+use winit::{
+    dpi::{LogicalSize, PhysicalSize},
+    event::{Event, WindowEvent},
+    event_loop::{ControlFlow, EventLoop},
+    window::WindowBuilder,
+};
+
+/// Runs the given command (or `ls -l --color=always` by default)
+/// and shows its output in a Terminal
+fn main() {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    if args.is_empty() {
+        args = vec![
+            "ls".to_owned(),
+            "-l".to_owned(),
+            "--color=always".to_owned(),
+        ];
+    }
+    let mut output = vec![];
+    Command::new(&args[0])
+        .args(&args[1..])
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap()
+        .stdout
+        .unwrap()
+        .read_to_end(&mut output)
+        .unwrap();
+
+    let width = 800;
+    let height = 600;
+    let logical_size = LogicalSize { width, height };
+    let event_loop = EventLoop::new();
+    let window = WindowBuilder::new()
+        .with_inner_size(logical_size)
+        .build(&event_loop)
+        .unwrap();
+    let physical_size = PhysicalSize::from_logical(logical_size, window.scale_factor());
+    let mut graphics = block_on(Graphics2D::new(
+        physical_size.width,
+        physical_size.height,
+        &window,
+    ))
+    .unwrap();
+    graphics.set_scale([logical_size.width as f32, logical_size.height as f32]);
+
+    let dim = graphics.init_text_grid(100).unwrap();
+    let mut terminal = Terminal::new(dim);
+    // Subprocesses usually only emit '\n', so move back to the first column too
+    for line in output.split(|b| *b == b'\n') {
+        terminal.feed(line);
+        terminal.feed(b"\r\n");
+    }
+    terminal.draw(&mut graphics).unwrap();
+    graphics.flush().unwrap();
+
+    event_loop.run(move |event, _, control_flow| {
+        *control_flow = ControlFlow::Wait;
+        match event {
+            Event::RedrawRequested(_) => {
+                graphics.force_render().unwrap();
+            }
+            Event::WindowEvent {
+                event: WindowEvent::CloseRequested,
+                ..
+            } => {
+                *control_flow = ControlFlow::Exit;
+            }
+            _ => {}
+        }
+    });
+}
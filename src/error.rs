@@ -47,13 +47,13 @@ impl Error for A2DError {}
 
 impl From<std::io::Error> for A2DError {
     fn from(e: std::io::Error) -> Self {
-        A2DError::new(format!("IOError"), Some(Box::new(e)))
+        A2DError::new("IOError".to_string(), Some(Box::new(e)))
     }
 }
 
 impl From<image::ImageError> for A2DError {
     fn from(e: image::ImageError) -> Self {
-        A2DError::new(format!("ImageError"), Some(Box::new(e)))
+        A2DError::new("ImageError".to_string(), Some(Box::new(e)))
     }
 }
 
@@ -62,3 +62,9 @@ impl From<wgpu::BufferAsyncErr> for A2DError {
         A2DError::new(format!("BufferAsyncErr: {:?}", e), None)
     }
 }
+
+impl From<ab_glyph::InvalidFont> for A2DError {
+    fn from(e: ab_glyph::InvalidFont) -> Self {
+        A2DError::new("InvalidFont".to_string(), Some(Box::new(e)))
+    }
+}
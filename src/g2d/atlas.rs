@@ -0,0 +1,226 @@
+use super::*;
+use ab_glyph::Font as _;
+use ab_glyph::ScaleFont as _;
+use std::collections::HashMap;
+
+const ATLAS_NROWS: usize = 64;
+const ATLAS_NCOLS: usize = 64;
+
+/// Cell holding the box drawn for characters the font doesn't have,
+/// and for any character once the atlas is full
+const FALLBACK_INDEX: usize = 0;
+
+/// A sheet of glyphs rasterized from a Font on demand
+///
+/// The sheet is a uniform grid of equally sized cells, one glyph
+/// per cell, so it can be used with a regular Batch.
+///
+/// In monospace mode, cells are as wide as an 'M' and glyphs are centered
+/// (and squeezed if needed) to fit. Otherwise cells are wide enough for
+/// most glyphs, which are drawn with their origin `padding` pixels from
+/// the left edge of the cell.
+pub(super) struct GlyphAtlas {
+    font: Font,
+    sheet: Rc<Sheet>,
+    px_height: f32,
+    monospace: bool,
+    padding: u32,
+    cell_width: u32,
+    cell_height: u32,
+    indices: HashMap<char, usize>,
+    len: usize,
+}
+
+impl GlyphAtlas {
+    pub fn new(
+        graphics: &mut Graphics2D,
+        font: &Font,
+        px_height: u32,
+        monospace: bool,
+    ) -> Result<Self> {
+        let scaled = font.inner().as_scaled(px_height as f32);
+        let cell_height = px_height.max(1);
+        let (padding, cell_width) = if monospace {
+            let m_width = scaled.h_advance(font.inner().glyph_id('M'));
+            (0, m_width.ceil().max(1.0) as u32)
+        } else {
+            // Leave room for glyphs that extend past their origin or advance
+            let padding = cell_height / 4;
+            (padding, cell_height + 2 * padding)
+        };
+        let width = cell_width * ATLAS_NCOLS as u32;
+        let height = cell_height * ATLAS_NROWS as u32;
+        let sheet = Sheet::from_rgba_bytes(
+            graphics,
+            width,
+            height,
+            vec![0; (width * height * 4) as usize],
+        )?;
+        let atlas = Self {
+            font: font.clone(),
+            sheet,
+            px_height: px_height as f32,
+            monospace,
+            padding,
+            cell_width,
+            cell_height,
+            indices: HashMap::new(),
+            len: FALLBACK_INDEX + 1,
+        };
+        atlas.write_cell(
+            graphics.renderer.gpu(),
+            FALLBACK_INDEX,
+            &atlas.fallback_pixels(),
+        );
+        Ok(atlas)
+    }
+
+    pub fn sheet(&self) -> &Rc<Sheet> {
+        &self.sheet
+    }
+
+    pub fn nrows(&self) -> usize {
+        ATLAS_NROWS
+    }
+
+    pub fn ncols(&self) -> usize {
+        ATLAS_NCOLS
+    }
+
+    pub fn font(&self) -> &Font {
+        &self.font
+    }
+
+    /// The pixel height glyphs are rasterized at
+    pub fn px_height(&self) -> f32 {
+        self.px_height
+    }
+
+    /// Distance in pixels between the left edge of a cell and
+    /// the origin of its glyph
+    pub fn padding(&self) -> f32 {
+        self.padding as f32
+    }
+
+    pub fn cell_size(&self) -> [f32; 2] {
+        [self.cell_width as f32, self.cell_height as f32]
+    }
+
+    /// Height of a cell divided by its width
+    pub fn cell_aspect_ratio(&self) -> f32 {
+        self.cell_height as f32 / self.cell_width as f32
+    }
+
+    /// Index of the (empty) cell for ' '
+    pub fn empty_index(&self) -> usize {
+        // The empty cell is never written to, so any unused cell works
+        ATLAS_NROWS * ATLAS_NCOLS - 1
+    }
+
+    /// Returns the index of the cell containing the given character,
+    /// rasterizing it into the atlas if this is the first time it's used
+    pub fn index(&mut self, gpu: Option<&Gpu>, ch: char) -> usize {
+        if ch == ' ' {
+            return self.empty_index();
+        }
+        if let Some(&index) = self.indices.get(&ch) {
+            return index;
+        }
+        let index = if self.len >= self.empty_index() {
+            FALLBACK_INDEX
+        } else {
+            match self.rasterize(ch) {
+                Some(pixels) => {
+                    let index = self.len;
+                    self.len += 1;
+                    self.write_cell(gpu, index, &pixels);
+                    index
+                }
+                None => FALLBACK_INDEX,
+            }
+        };
+        self.indices.insert(ch, index);
+        index
+    }
+
+    /// Draws the glyph for the given character into a buffer the size of
+    /// a cell. Returns None if the font does not have the character.
+    fn rasterize(&self, ch: char) -> Option<Vec<u8>> {
+        let font = self.font.inner();
+        let id = font.glyph_id(ch);
+        if id.0 == 0 {
+            return None;
+        }
+        let scaled = font.as_scaled(self.px_height);
+        let advance = scaled.h_advance(id);
+        let cell_width = self.cell_width as f32;
+
+        let (x_scale, x_offset) = if self.monospace {
+            // Glyphs wider than the cell (e.g. CJK) are squeezed horizontally
+            // to fit, and narrower ones are centered
+            let x_scale = if advance > cell_width {
+                cell_width / advance
+            } else {
+                1.0
+            };
+            (x_scale, (cell_width - advance * x_scale) / 2.0)
+        } else {
+            (1.0, self.padding as f32)
+        };
+        let glyph = id.with_scale_and_position(
+            ab_glyph::PxScale {
+                x: self.px_height * x_scale,
+                y: self.px_height,
+            },
+            ab_glyph::point(x_offset, scaled.ascent()),
+        );
+
+        let mut pixels = vec![0; (self.cell_width * self.cell_height * 4) as usize];
+        if let Some(outlined) = font.outline_glyph(glyph) {
+            let bounds = outlined.px_bounds();
+            outlined.draw(|x, y, coverage| {
+                let x = x as i64 + bounds.min.x as i64;
+                let y = y as i64 + bounds.min.y as i64;
+                if x >= 0 && y >= 0 && x < self.cell_width as i64 && y < self.cell_height as i64 {
+                    let i = ((y as u32 * self.cell_width + x as u32) * 4) as usize;
+                    let alpha = (coverage.min(1.0) * 255.0) as u8;
+                    pixels[i..i + 4].copy_from_slice(&[255, 255, 255, alpha]);
+                }
+            });
+        }
+        Some(pixels)
+    }
+
+    /// An outlined box, commonly used to show a missing glyph
+    fn fallback_pixels(&self) -> Vec<u8> {
+        let (w, h) = (self.cell_width, self.cell_height);
+        let inset_x = w / 8;
+        let inset_y = h / 8;
+        let thickness = (w / 12).max(1);
+        let mut pixels = vec![0; (w * h * 4) as usize];
+        for y in inset_y..h - inset_y {
+            for x in inset_x..w - inset_x {
+                let on_edge = x < inset_x + thickness
+                    || x >= w - inset_x - thickness
+                    || y < inset_y + thickness
+                    || y >= h - inset_y - thickness;
+                if on_edge {
+                    let i = ((y * w + x) * 4) as usize;
+                    pixels[i..i + 4].copy_from_slice(&[255, 255, 255, 255]);
+                }
+            }
+        }
+        pixels
+    }
+
+    fn write_cell(&self, gpu: Option<&Gpu>, index: usize, pixels: &[u8]) {
+        let row = (index / ATLAS_NCOLS) as u32;
+        let col = (index % ATLAS_NCOLS) as u32;
+        self.sheet.write_rgba(
+            gpu,
+            [col * self.cell_width, row * self.cell_height],
+            [self.cell_width, self.cell_height],
+            pixels,
+        );
+    }
+}
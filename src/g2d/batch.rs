@@ -1,20 +1,57 @@
 use super::*;
 use std::rc::Rc;
 
-pub(super) struct Batch {
+/// A collection of sprites that share a single sheet and are drawn
+/// together with one instanced draw call
+///
+/// The sheet is divided into a uniform grid of `nrows x ncols` cells,
+/// and each sprite selects its source image either by cell index
+/// or with an arbitrary rectangle of the sheet
+pub struct Batch {
     sheet: Rc<Sheet>,
-    instance_buffer: wgpu::Buffer,
+    instance_buffer: InstanceBuffer,
+
+    /// CPU side copy of every instance, so that the buffer can be
+    /// rebuilt when the batch outgrows it
+    instances: Vec<Instance>,
+
+    /// Indices of slots freed by `remove`, reused by `push`
+    free_list: Vec<usize>,
+
+    /// For every instance, whether its slot is in `free_list`
+    freed: Vec<bool>,
+
+    /// Indirection table that SpriteHandles resolve through
+    handles: Vec<HandleEntry>,
+
+    /// For every instance, the entry of `handles` that refers to it
+    instance_handles: Vec<usize>,
+
+    /// Entries of `handles` that no longer refer to an instance
+    free_handles: Vec<usize>,
+
     scale: Scaling,
     translation: Translation,
+    rotation: f32,
+    pivot: [f32; 2],
+    tint: Color,
+    opacity: f32,
+    follows_camera: bool,
+    blend_mode: BlendMode,
+    shader: Option<Rc<Shader>>,
+    shader_params: Vec<f32>,
     nrows: usize,
     ncols: usize,
-    len: usize,
 
-    pending_updates: Vec<(usize, SpriteUpdate)>,
+    /// Indices of instances that changed since the last flush
+    pending_updates: Vec<usize>,
 }
 
-#[allow(dead_code)]
 impl Batch {
+    /// Creates a new batch whose sprites are described by `descs`
+    ///
+    /// The batch is not drawn until it is placed in a slot with
+    /// `Graphics2D::set_batch`
     pub fn new(
         graphics: &mut Graphics2D,
         sheet: Rc<Sheet>,
@@ -24,38 +61,67 @@ impl Batch {
     ) -> Self {
         let mut instances = vec![];
         for desc in descs {
-            let src = src_index_to_rect(nrows, ncols, desc.src);
-            instances.push(
-                Instance::builder()
-                    .src(src)
-                    .dest(desc.dst)
-                    .rotate(desc.rotate)
-                    .color_factor(desc.color)
-                    .build(),
-            );
+            instances.push(desc_to_instance(nrows, ncols, desc));
         }
-
-        let instance_buffer = graphics.device.create_buffer_with_data(
-            bytemuck::cast_slice(&instances),
-            wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::MAP_WRITE,
-        );
+        let instance_buffer = match graphics.renderer.gpu() {
+            Some(gpu) => {
+                let capacity = instances.len().max(1);
+                InstanceBuffer::Gpu {
+                    buffer: create_instance_buffer(&gpu.device, &instances, capacity),
+                    capacity,
+                    len: instances.len(),
+                }
+            }
+            None => InstanceBuffer::Soft(instances.clone()),
+        };
+        let handles = (0..instances.len())
+            .map(|i| HandleEntry {
+                generation: 0,
+                index: Some(i),
+            })
+            .collect();
+        let instance_handles = (0..instances.len()).collect();
+        let freed = vec![false; instances.len()];
 
         Self {
             sheet,
             instance_buffer,
+            instances,
+            free_list: vec![],
+            freed,
+            handles,
+            instance_handles,
+            free_handles: vec![],
             scale: [1.0, 1.0],
             translation: [0.0, 0.0],
+            rotation: 0.0,
+            pivot: [0.0, 0.0],
+            tint: [1.0, 1.0, 1.0, 1.0].into(),
+            opacity: 1.0,
+            follows_camera: true,
+            blend_mode: BlendMode::Alpha,
+            shader: None,
+            shader_params: vec![],
             nrows,
             ncols,
-            len: instances.len(),
             pending_updates: vec![],
         }
     }
 
-    pub fn sheet(&self) -> &Sheet {
+    pub fn sheet(&self) -> &Rc<Sheet> {
         &self.sheet
     }
 
+    /// The number of rows the sheet is divided into
+    pub fn nrows(&self) -> usize {
+        self.nrows
+    }
+
+    /// The number of columns the sheet is divided into
+    pub fn ncols(&self) -> usize {
+        self.ncols
+    }
+
     /// The scaling that's applied before performing the batch translation
     /// This allows scaling the size of all elements in a batch at once
     /// independent of all other batches
@@ -75,49 +141,499 @@ impl Batch {
         self.translation = translation;
     }
 
-    pub fn get(&mut self, i: usize) -> SpriteView {
+    /// Clockwise rotation in radians of the whole batch around the pivot
+    pub fn rotation(&self) -> f32 {
+        self.rotation
+    }
+
+    pub fn set_rotation(&mut self, rotation: f32) {
+        self.rotation = rotation;
+    }
+
+    /// The point that the batch is scaled and rotated around,
+    /// in the same coordinates as the sprites' destination rectangles
+    ///
+    /// Altogether, a point `p` of a sprite ends up at
+    /// `pivot + rotate((p - pivot) * scale) + translation`
+    pub fn pivot(&self) -> [f32; 2] {
+        self.pivot
+    }
+
+    pub fn set_pivot(&mut self, pivot: [f32; 2]) {
+        self.pivot = pivot;
+    }
+
+    /// Color factor multiplied with the color of every sprite in the batch
+    pub fn tint(&self) -> Color {
+        self.tint
+    }
+
+    pub fn set_tint<C: Into<Color>>(&mut self, tint: C) {
+        self.tint = tint.into();
+    }
+
+    /// Multiplied with the alpha of every sprite in the batch,
+    /// for fading a whole layer in or out
+    pub fn opacity(&self) -> f32 {
+        self.opacity
+    }
+
+    pub fn set_opacity(&mut self, opacity: f32) {
+        self.opacity = opacity;
+    }
+
+    /// Whether the batch is drawn through the Graphics2D's camera,
+    /// or directly in screen coordinates (e.g. for a HUD).
+    /// Defaults to true
+    pub fn follows_camera(&self) -> bool {
+        self.follows_camera
+    }
+
+    pub fn set_follows_camera(&mut self, follows_camera: bool) {
+        self.follows_camera = follows_camera;
+    }
+
+    /// How the batch's sprites are combined with what's drawn below them
+    pub fn blend_mode(&self) -> BlendMode {
+        self.blend_mode
+    }
+
+    pub fn set_blend_mode(&mut self, blend_mode: BlendMode) {
+        self.blend_mode = blend_mode;
+    }
+
+    /// The custom fragment shader the batch is drawn with, if any
+    pub fn shader(&self) -> Option<&Rc<Shader>> {
+        self.shader.as_ref()
+    }
+
+    /// Draws the batch with a custom fragment shader,
+    /// or with the builtin one if None
+    pub fn set_shader(&mut self, shader: Option<Rc<Shader>>) {
+        self.shader = shader;
+    }
+
+    pub fn shader_params(&self) -> &[f32] {
+        &self.shader_params
+    }
+
+    /// Sets the contents of the custom shader's `Params` uniform block
+    /// (see `Shader`)
+    pub fn set_shader_params(&mut self, params: &[f32]) {
+        self.shader_params = params.to_vec();
+    }
+
+    pub(super) fn pipeline_key(&self, format: wgpu::TextureFormat) -> PipelineKey {
+        PipelineKey {
+            format,
+            blend_mode: self.blend_mode,
+            shader: self.shader.as_ref().map(|shader| shader.id()),
+        }
+    }
+
+    /// The contents of the per-batch uniform buffer
+    pub(super) fn uniform(&self) -> BatchUniform {
+        let [r, g, b, a] = self.tint.to_array();
+        BatchUniform {
+            scale: self.scale,
+            translation: self.translation,
+            pivot: self.pivot,
+            rotation: self.rotation,
+            _padding: 0.0,
+            tint: [r, g, b, a * self.opacity],
+        }
+    }
+
+    /// Returns a view for updating the sprite at the given index
+    /// Panics if the index is out of bounds, or if the sprite there
+    /// was removed with `remove` and the slot hasn't been reused yet
+    pub fn get(&mut self, i: usize) -> SpriteView<'_> {
+        assert!(
+            i < self.len(),
+            "Sprite index out of bounds ({} >= {})",
+            i,
+            self.len()
+        );
+        assert!(
+            !self.freed[i],
+            "Sprite {} was removed and its slot hasn't been reused yet",
+            i
+        );
         SpriteView { batch: self, i }
     }
 
+    /// The number of sprite slots in the batch, including
+    /// slots freed by `remove` that haven't been reused yet
     pub fn len(&self) -> usize {
-        self.len
+        self.instances.len()
     }
 
-    pub fn instance_buffer(&self) -> &wgpu::Buffer {
-        &self.instance_buffer
+    pub fn is_empty(&self) -> bool {
+        self.instances.is_empty()
     }
 
-    pub async fn flush(&mut self) -> Result<()> {
-        let updates = std::mem::replace(&mut self.pending_updates, vec![]);
-        if updates.is_empty() {
-            return Ok(());
-        }
-        let min_i = updates.iter().map(|(i, _)| *i).min().unwrap();
-        let max_i = updates.iter().map(|(i, _)| *i).max().unwrap();
-        let mut inst_mapping = self
-            .instance_buffer
-            .map_write(
-                (min_i * std::mem::size_of::<Instance>()) as wgpu::BufferAddress,
-                ((max_i - min_i + 1) * std::mem::size_of::<Instance>()) as wgpu::BufferAddress,
-            )
-            .await?;
-        let inst_arr = inst_mapping.as_slice();
-        for (i, update) in updates {
-            let start = (i - min_i) * std::mem::size_of::<Instance>();
-            let end = (i - min_i + 1) * std::mem::size_of::<Instance>();
-            let inst: &mut Instance = bytemuck::from_bytes_mut(&mut inst_arr[start..end]);
-            match update {
-                SpriteUpdate::Src(src) => inst.set_src(src),
-                SpriteUpdate::Dst(dst) => inst.set_dest(dst),
-                SpriteUpdate::Rotate(rot) => inst.set_rotation(rot),
-                SpriteUpdate::Color(color) => inst.set_color_factor(color),
+    /// Adds a sprite and returns its index.
+    ///
+    /// Slots freed by `remove` are reused first, otherwise the sprite
+    /// is added at the end. If the batch outgrows its buffer, a larger
+    /// one is allocated on the next flush.
+    pub fn push(&mut self, desc: &SpriteDesc) -> usize {
+        let instance = desc_to_instance(self.nrows, self.ncols, desc);
+        let i = match self.free_list.pop() {
+            Some(i) => {
+                self.instances[i] = instance;
+                let h = self.alloc_handle(i);
+                self.instance_handles[i] = h;
+                self.freed[i] = false;
+                i
+            }
+            None => {
+                let i = self.instances.len();
+                self.instances.push(instance);
+                let h = self.alloc_handle(i);
+                self.instance_handles.push(h);
+                self.freed.push(false);
+                i
             }
+        };
+        self.pending_updates.push(i);
+        i
+    }
+
+    /// Adds a sprite and returns a handle that keeps referring to it
+    /// even when other sprites are removed and the batch is compacted
+    pub fn insert(&mut self, desc: &SpriteDesc) -> SpriteHandle {
+        let i = self.push(desc);
+        self.handle(i)
+    }
+
+    /// Returns a handle for the sprite currently at the given index
+    /// Panics if the index is out of bounds, or if the sprite there
+    /// was removed with `remove` and the slot hasn't been reused yet
+    pub fn handle(&self, i: usize) -> SpriteHandle {
+        assert!(i < self.len(), "Sprite index out of bounds");
+        assert!(
+            !self.freed[i],
+            "Sprite {} was removed and its slot hasn't been reused yet",
+            i
+        );
+        let h = self.instance_handles[i];
+        SpriteHandle {
+            index: h as u32,
+            generation: self.handles[h].generation,
+        }
+    }
+
+    /// Returns the current index of the sprite the handle refers to,
+    /// or None if the sprite has been removed
+    pub fn index_of(&self, handle: SpriteHandle) -> Option<usize> {
+        match self.handles.get(handle.index as usize) {
+            Some(entry) if entry.generation == handle.generation => entry.index,
+            _ => None,
         }
-        Ok(())
     }
+
+    /// Returns true if the sprite the handle refers to is still in the batch
+    pub fn contains(&self, handle: SpriteHandle) -> bool {
+        self.index_of(handle).is_some()
+    }
+
+    /// Returns a view for updating the sprite the handle refers to,
+    /// or None if the sprite has been removed
+    pub fn sprite(&mut self, handle: SpriteHandle) -> Option<SpriteView<'_>> {
+        let i = self.index_of(handle)?;
+        Some(SpriteView { batch: self, i })
+    }
+
+    /// Removes the sprite the handle refers to, compacting the batch.
+    /// Returns false if the sprite had already been removed
+    pub fn remove_sprite(&mut self, handle: SpriteHandle) -> bool {
+        match self.index_of(handle) {
+            Some(i) => {
+                self.swap_remove(i);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes the sprite at the given index by moving the last
+    /// sprite into its place, so the batch never has gaps.
+    /// The sprite that was last now has index `i`, but handles
+    /// to it remain valid.
+    pub fn swap_remove(&mut self, i: usize) {
+        assert!(i < self.len(), "Sprite index out of bounds");
+        let last = self.instances.len() - 1;
+        // The handle of a slot freed by `remove` has already been freed
+        let last_is_free = self.freed[last];
+        if self.freed[i] {
+            self.free_list.retain(|&j| j != i);
+        } else {
+            self.free_handle(self.instance_handles[i]);
+        }
+        if last_is_free {
+            for j in self.free_list.iter_mut() {
+                if *j == last {
+                    *j = i;
+                }
+            }
+        }
+        self.instances.swap_remove(i);
+        self.instance_handles.swap_remove(i);
+        self.freed.swap_remove(i);
+        if i < self.instances.len() {
+            if !last_is_free {
+                self.handles[self.instance_handles[i]].index = Some(i);
+            }
+            self.pending_updates.push(i);
+        }
+    }
+
+    /// Removes the sprite at the given index by hiding it and
+    /// remembering the slot for the next `push` (until then,
+    /// `get` panics for that index).
+    /// All other sprites keep their indices.
+    pub fn remove(&mut self, i: usize) {
+        assert!(i < self.len(), "Sprite index out of bounds");
+        if self.freed[i] {
+            return;
+        }
+        self.free_handle(self.instance_handles[i]);
+        self.instances[i] = bytemuck::Zeroable::zeroed();
+        self.free_list.push(i);
+        self.freed[i] = true;
+        self.pending_updates.push(i);
+    }
+
+    /// Removes all sprites
+    pub fn clear(&mut self) {
+        for i in 0..self.instances.len() {
+            if !self.freed[i] {
+                self.free_handle(self.instance_handles[i]);
+            }
+        }
+        self.instances.clear();
+        self.instance_handles.clear();
+        self.free_list.clear();
+        self.freed.clear();
+        self.pending_updates.clear();
+    }
+
+    fn alloc_handle(&mut self, i: usize) -> usize {
+        match self.free_handles.pop() {
+            Some(h) => {
+                self.handles[h].index = Some(i);
+                h
+            }
+            None => {
+                self.handles.push(HandleEntry {
+                    generation: 0,
+                    index: Some(i),
+                });
+                self.handles.len() - 1
+            }
+        }
+    }
+
+    /// Invalidates all outstanding handles to the entry
+    fn free_handle(&mut self, h: usize) {
+        let entry = &mut self.handles[h];
+        entry.generation = entry.generation.wrapping_add(1);
+        entry.index = None;
+        self.free_handles.push(h);
+    }
+
+    /// The GPU buffer and the number of instances it held
+    /// as of the last flush
+    pub(super) fn instance_buffer(&self) -> (&wgpu::Buffer, usize) {
+        match &self.instance_buffer {
+            InstanceBuffer::Gpu { buffer, len, .. } => (buffer, *len),
+            InstanceBuffer::Soft(_) => {
+                panic!("Batch created for the software renderer drawn on the GPU")
+            }
+        }
+    }
+
+    /// The instances as of the last flush, for the software renderer
+    pub(super) fn flushed_instances(&self) -> &[Instance] {
+        match &self.instance_buffer {
+            InstanceBuffer::Soft(instances) => instances,
+            InstanceBuffer::Gpu { .. } => {
+                panic!("Batch created on the GPU drawn by the software renderer")
+            }
+        }
+    }
+
+    /// Brings the instances drawn by the software renderer
+    /// up to date with all changes made since the last flush
+    pub(super) fn flush_soft(&mut self) {
+        if let InstanceBuffer::Soft(flushed) = &mut self.instance_buffer {
+            flushed.clear();
+            flushed.extend_from_slice(&self.instances);
+        }
+        self.pending_updates.clear();
+    }
+
+    /// Records the copies needed to bring the GPU buffer up to date
+    /// with all changes made since the last flush
+    ///
+    /// Changes to the same sprite are merged, and the changed sprites
+    /// are grouped into contiguous ranges that are uploaded together
+    /// through a single staging buffer
+    pub(super) fn flush(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder) {
+        let (instance_buffer, capacity) = match &mut self.instance_buffer {
+            InstanceBuffer::Gpu {
+                buffer,
+                capacity,
+                len,
+            } => {
+                *len = self.instances.len();
+                (buffer, capacity)
+            }
+            InstanceBuffer::Soft(_) => {
+                panic!("Batch created for the software renderer drawn on the GPU")
+            }
+        };
+        if self.instances.len() > *capacity {
+            // Grow geometrically so that pushing many sprites one at a time
+            // doesn't reallocate on every flush
+            *capacity = self.instances.len().max(*capacity * 2);
+            *instance_buffer = create_instance_buffer(device, &self.instances, *capacity);
+            self.pending_updates.clear();
+            return;
+        }
+        let mut updates = std::mem::take(&mut self.pending_updates);
+        let len = self.instances.len();
+        updates.retain(|&i| i < len);
+        if updates.is_empty() {
+            return;
+        }
+        updates.sort_unstable();
+        updates.dedup();
+        let ranges = dirty_ranges(&updates);
+
+        let mut staging_data: Vec<Instance> = vec![];
+        for &(start, end) in &ranges {
+            staging_data.extend_from_slice(&self.instances[start..end]);
+        }
+        let staging_buffer = device.create_buffer_with_data(
+            bytemuck::cast_slice(&staging_data),
+            wgpu::BufferUsage::COPY_SRC,
+        );
+
+        let inst_size = std::mem::size_of::<Instance>();
+        let mut staging_offset = 0;
+        for (start, end) in ranges {
+            let size = (end - start) * inst_size;
+            encoder.copy_buffer_to_buffer(
+                &staging_buffer,
+                staging_offset as wgpu::BufferAddress,
+                instance_buffer,
+                (start * inst_size) as wgpu::BufferAddress,
+                size as wgpu::BufferAddress,
+            );
+            staging_offset += size;
+        }
+    }
+}
+
+/// Where the instances of a batch are drawn from
+enum InstanceBuffer {
+    Gpu {
+        buffer: wgpu::Buffer,
+
+        /// Number of instances the buffer has room for
+        capacity: usize,
+
+        /// Number of instances the buffer held as of the last flush
+        len: usize,
+    },
+
+    /// Copy of the instances as of the last flush
+    Soft(Vec<Instance>),
+}
+
+/// Per-batch uniform data, laid out to match `BatchUniform`
+/// in shader.vert (std140)
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub(super) struct BatchUniform {
+    scale: Scaling,
+    translation: Translation,
+    pivot: [f32; 2],
+    rotation: f32,
+    _padding: f32,
+    tint: [f32; 4],
+}
+
+unsafe impl bytemuck::Pod for BatchUniform {}
+unsafe impl bytemuck::Zeroable for BatchUniform {}
+
+/// Clean instances between two dirty ones are uploaded along with them
+/// if there are at most this many, since a slightly larger copy is cheaper
+/// than an extra copy command
+const DIRTY_RANGE_MERGE_GAP: usize = 16;
+
+/// Groups sorted, deduplicated indices into half-open ranges
+fn dirty_ranges(indices: &[usize]) -> Vec<(usize, usize)> {
+    let mut ranges: Vec<(usize, usize)> = vec![];
+    for &i in indices {
+        match ranges.last_mut() {
+            Some((_, end)) if i <= *end + DIRTY_RANGE_MERGE_GAP => *end = i + 1,
+            _ => ranges.push((i, i + 1)),
+        }
+    }
+    ranges
+}
+
+fn desc_to_instance(nrows: usize, ncols: usize, desc: &SpriteDesc) -> Instance {
+    Instance::builder()
+        .src(desc.src_rect_in(nrows, ncols))
+        .flip(desc.flip)
+        .dest(desc.dst)
+        .rotate(desc.rotate)
+        .color_factor(desc.color)
+        .z(desc.z)
+        .visible(desc.visible)
+        .transform(desc.transform)
+        .tiling(desc.tiling)
+        .build()
+}
+
+/// Creates a buffer with room for `capacity` instances, starting
+/// with the given instances
+fn create_instance_buffer(
+    device: &wgpu::Device,
+    instances: &[Instance],
+    capacity: usize,
+) -> wgpu::Buffer {
+    let mut data: Vec<Instance> = instances.to_vec();
+    data.resize(capacity, bytemuck::Zeroable::zeroed());
+    device.create_buffer_with_data(
+        bytemuck::cast_slice(&data),
+        wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
+    )
+}
+
+/// A stable reference to a sprite in a Batch
+///
+/// Unlike an index, a handle keeps referring to the same sprite
+/// while other sprites are removed. Once its own sprite is removed,
+/// the handle is invalidated and never refers to another sprite.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SpriteHandle {
+    index: u32,
+    generation: u32,
+}
+
+struct HandleEntry {
+    generation: u32,
+
+    /// Index of the instance this entry refers to, if any
+    index: Option<usize>,
 }
 
-fn src_index_to_rect(nrows: usize, ncols: usize, index: usize) -> Rect {
+pub(crate) fn src_index_to_rect(nrows: usize, ncols: usize, index: usize) -> Rect {
     let rwidth = 1.0 / (ncols as f32);
     let rheight = 1.0 / (nrows as f32);
     let col = (index % ncols) as f32;
@@ -130,47 +646,218 @@ fn src_index_to_rect(nrows: usize, ncols: usize, index: usize) -> Rect {
     ]
     .into()
 }
-pub(super) struct SpriteView<'a> {
+
+/// A handle for updating a single sprite in a batch
+///
+/// Updates are recorded and only take effect after
+/// `Graphics2D::flush` is called
+pub struct SpriteView<'a> {
     batch: &'a mut Batch,
     i: usize,
 }
 
-#[allow(dead_code)]
 impl<'a> SpriteView<'a> {
+    fn instance(&mut self) -> &mut Instance {
+        self.batch.pending_updates.push(self.i);
+        &mut self.batch.instances[self.i]
+    }
+
+    /// Sets the cell of the batch's sheet to use as the image
     pub fn src(&mut self, src_index: usize) -> &mut Self {
         let src = src_index_to_rect(self.batch.nrows, self.batch.ncols, src_index);
-        self.batch
-            .pending_updates
-            .push((self.i, SpriteUpdate::Src(src)));
+        self.instance().set_src(src);
+        self
+    }
+
+    /// Sets an arbitrary rectangle of the sheet to use as the image,
+    /// in normalized coordinates (between 0 and 1)
+    pub fn src_rect<R: Into<Rect>>(&mut self, src: R) -> &mut Self {
+        self.instance().set_src(src);
+        self
+    }
+
+    /// Sets an arbitrary rectangle of the sheet to use as the image,
+    /// in pixels of the sheet
+    pub fn src_pixels<R: Into<Rect>>(&mut self, src: R) -> &mut Self {
+        let src = src.into();
+        let width = self.batch.sheet.width() as f32;
+        let height = self.batch.sheet.height() as f32;
+        let [x1, y1] = src.upper_left();
+        let [x2, y2] = src.lower_right();
+        self.src_rect([x1 / width, y1 / height, x2 / width, y2 / height])
+    }
+
+    /// Mirrors the image horizontally and/or vertically.
+    /// The flip is kept when the source is changed later
+    pub fn flip(&mut self, horizontal: bool, vertical: bool) -> &mut Self {
+        self.instance().set_flip([horizontal, vertical]);
         self
     }
 
     pub fn dst<R: Into<Rect>>(&mut self, dst: R) -> &mut Self {
-        self.batch
-            .pending_updates
-            .push((self.i, SpriteUpdate::Dst(dst.into())));
+        self.instance().set_dest(dst);
         self
     }
 
     pub fn rotate(&mut self, rotate: f32) -> &mut Self {
-        self.batch
-            .pending_updates
-            .push((self.i, SpriteUpdate::Rotate(rotate)));
+        self.instance().set_rotation(rotate);
         self
     }
 
     pub fn color<C: Into<Color>>(&mut self, color: C) -> &mut Self {
-        self.batch
-            .pending_updates
-            .push((self.i, SpriteUpdate::Color(color.into())));
+        self.instance().set_color_factor(color);
+        self
+    }
+
+    /// Sets the depth of the sprite (see `SpriteDesc::z`)
+    pub fn z(&mut self, z: f32) -> &mut Self {
+        self.instance().set_z(z);
+        self
+    }
+
+    /// Sets the affine transformation applied after the rotation
+    /// (see `SpriteDesc::transform`)
+    pub fn transform(&mut self, transform: Affine) -> &mut Self {
+        self.instance().set_transform(transform);
+        self
+    }
+
+    /// Repeats the source across the destination instead of
+    /// stretching it (see `SpriteDesc::tiling`)
+    pub fn tiling(&mut self, tiling: Option<Tiling>) -> &mut Self {
+        self.instance().set_tiling(tiling);
+        self
+    }
+
+    /// Scrolls the tiles of a tiled sprite, in units of one tile.
+    /// Has no effect on sprites that aren't tiled
+    pub fn tile_offset(&mut self, offset: [f32; 2]) -> &mut Self {
+        self.instance().set_tile_offset(offset);
+        self
+    }
+
+    /// Shows or hides the sprite without removing it
+    pub fn visible(&mut self, visible: bool) -> &mut Self {
+        self.instance().set_visible(visible);
         self
     }
 }
 
-#[derive(Debug)]
-pub(super) enum SpriteUpdate {
-    Src(Rect),
-    Dst(Rect),
-    Rotate(f32),
-    Color(Color),
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn batch(graphics: &mut Graphics2D, n: usize) -> Batch {
+        let sheet = Sheet::from_color(graphics, [1.0, 1.0, 1.0]).unwrap();
+        let descs: Vec<_> = (0..n)
+            .map(|i| SpriteDesc::new(0, [i as f32, 0.0, i as f32 + 1.0, 1.0]))
+            .collect();
+        Batch::new(graphics, sheet, 1, 1, &descs)
+    }
+
+    /// Checks that every live sprite has its own handle entry,
+    /// that each entry refers back to its sprite, and that `freed`
+    /// agrees with `free_list`
+    fn check_handles(batch: &Batch) {
+        let mut seen = std::collections::HashSet::new();
+        for i in 0..batch.len() {
+            if batch.freed[i] {
+                continue;
+            }
+            let handle = batch.handle(i);
+            assert!(seen.insert(handle.index));
+            assert_eq!(batch.index_of(handle), Some(i));
+        }
+        for &h in &batch.free_handles {
+            assert!(!seen.contains(&(h as u32)));
+            assert_eq!(batch.handles[h].index, None);
+        }
+        assert_eq!(batch.freed.len(), batch.len());
+        for &i in &batch.free_list {
+            assert!(batch.freed[i]);
+        }
+        let nfreed = batch.freed.iter().filter(|&&freed| freed).count();
+        assert_eq!(nfreed, batch.free_list.len());
+        let mut free_handles = batch.free_handles.clone();
+        free_handles.sort_unstable();
+        free_handles.dedup();
+        assert_eq!(free_handles.len(), batch.free_handles.len());
+    }
+
+    #[test]
+    fn swap_remove_removed_slot() {
+        let mut graphics = Graphics2D::new_software(1, 1);
+        let mut batch = batch(&mut graphics, 3);
+        batch.remove(1);
+        batch.swap_remove(1);
+        check_handles(&batch);
+        let a = batch.insert(&SpriteDesc::new(0, [0.0, 0.0, 1.0, 1.0]));
+        let b = batch.insert(&SpriteDesc::new(0, [1.0, 0.0, 2.0, 1.0]));
+        check_handles(&batch);
+        assert_ne!(a, b);
+        assert_eq!(batch.index_of(a), Some(2));
+        assert_eq!(batch.index_of(b), Some(3));
+    }
+
+    #[test]
+    fn swap_remove_moves_a_removed_slot() {
+        let mut graphics = Graphics2D::new_software(1, 1);
+        let mut batch = batch(&mut graphics, 3);
+        let first = batch.handle(0);
+        batch.remove(2);
+        batch.swap_remove(0);
+        check_handles(&batch);
+        assert!(!batch.contains(first));
+        // The removed slot moved to index 0 and is reused first
+        let a = batch.insert(&SpriteDesc::new(0, [0.0, 0.0, 1.0, 1.0]));
+        assert_eq!(batch.index_of(a), Some(0));
+        check_handles(&batch);
+    }
+
+    #[test]
+    fn handles_survive_removals() {
+        let mut graphics = Graphics2D::new_software(1, 1);
+        let mut batch = batch(&mut graphics, 0);
+        let handles: Vec<_> = (0..6)
+            .map(|i| batch.insert(&SpriteDesc::new(0, [i as f32, 0.0, i as f32 + 1.0, 1.0])))
+            .collect();
+        assert!(batch.remove_sprite(handles[1]));
+        assert!(!batch.remove_sprite(handles[1]));
+        batch.remove(batch.index_of(handles[3]).unwrap());
+        batch.swap_remove(batch.index_of(handles[5]).unwrap());
+        check_handles(&batch);
+        for (i, &handle) in handles.iter().enumerate() {
+            assert_eq!(batch.contains(handle), i % 2 == 0, "{}", i);
+        }
+        batch.clear();
+        check_handles(&batch);
+        assert!(handles.iter().all(|&handle| !batch.contains(handle)));
+    }
+
+    #[test]
+    fn get_reused_slot() {
+        let mut graphics = Graphics2D::new_software(1, 1);
+        let mut batch = batch(&mut graphics, 2);
+        batch.remove(0);
+        assert_eq!(batch.push(&SpriteDesc::new(0, [0.0, 0.0, 1.0, 1.0])), 0);
+        batch.get(0).src(0).flip(true, false);
+    }
+
+    #[test]
+    #[should_panic(expected = "was removed")]
+    fn get_removed_slot() {
+        let mut graphics = Graphics2D::new_software(1, 1);
+        let mut batch = batch(&mut graphics, 2);
+        batch.remove(0);
+        batch.get(0).src(0);
+    }
+
+    #[test]
+    #[should_panic(expected = "was removed")]
+    fn handle_of_removed_slot() {
+        let mut graphics = Graphics2D::new_software(1, 1);
+        let mut batch = batch(&mut graphics, 2);
+        batch.remove(0);
+        batch.handle(0);
+    }
 }
@@ -0,0 +1,80 @@
+/// How the colors of a batch's sprites are combined with
+/// what has already been drawn
+///
+/// Only `Alpha` and `Opaque` sprites hide the sprites with a lower z
+/// that are drawn after them (see `SpriteDesc::z`). Sprites blended
+/// with the other modes are meant to go over what's below, so they
+/// should be drawn last
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum BlendMode {
+    /// Regular transparency; the default
+    #[default]
+    Alpha,
+
+    /// Like `Alpha`, for sheets whose colors have already been
+    /// multiplied by their alpha
+    PremultipliedAlpha,
+
+    /// Adds the sprite's color (weighted by its alpha) to what's below,
+    /// for glows, sparks and other light effects
+    Additive,
+
+    /// Multiplies what's below by the sprite's color,
+    /// for shadows and lighting overlays.
+    ///
+    /// Apart from fully transparent pixels, which are discarded like
+    /// in every mode, the sprite's alpha is ignored: use white rather
+    /// than translucent pixels for areas that should stay unchanged
+    Multiply,
+
+    /// The inverse of multiplying the inverted colors. Always brightens.
+    ///
+    /// Apart from fully transparent pixels, which are discarded like
+    /// in every mode, the sprite's alpha is ignored: use black rather
+    /// than translucent pixels for areas that should stay unchanged
+    Screen,
+
+    /// Overwrites what's below, ignoring alpha
+    Opaque,
+}
+
+impl BlendMode {
+    /// Whether sprites drawn with this mode write to the depth buffer
+    pub(super) fn writes_depth(self) -> bool {
+        matches!(self, BlendMode::Alpha | BlendMode::Opaque)
+    }
+
+    pub(super) fn color_state(self, format: wgpu::TextureFormat) -> wgpu::ColorStateDescriptor {
+        use wgpu::BlendFactor as F;
+        let blend = |src_factor, dst_factor| wgpu::BlendDescriptor {
+            src_factor,
+            dst_factor,
+            operation: wgpu::BlendOperation::Add,
+        };
+        // Modes meant for effects on top of an image keep its alpha
+        let keep_alpha = blend(F::Zero, F::One);
+        let (color_blend, alpha_blend) = match self {
+            BlendMode::Alpha => (
+                blend(F::SrcAlpha, F::OneMinusSrcAlpha),
+                wgpu::BlendDescriptor::REPLACE,
+            ),
+            BlendMode::PremultipliedAlpha => (
+                blend(F::One, F::OneMinusSrcAlpha),
+                blend(F::One, F::OneMinusSrcAlpha),
+            ),
+            BlendMode::Additive => (blend(F::SrcAlpha, F::One), keep_alpha),
+            BlendMode::Multiply => (blend(F::DstColor, F::Zero), keep_alpha),
+            BlendMode::Screen => (blend(F::One, F::OneMinusSrcColor), keep_alpha),
+            BlendMode::Opaque => (
+                wgpu::BlendDescriptor::REPLACE,
+                wgpu::BlendDescriptor::REPLACE,
+            ),
+        };
+        wgpu::ColorStateDescriptor {
+            format,
+            color_blend,
+            alpha_blend,
+            write_mask: wgpu::ColorWrite::ALL,
+        }
+    }
+}
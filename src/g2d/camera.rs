@@ -0,0 +1,205 @@
+use super::*;
+
+/// Controls which part of the world user batches show
+///
+/// "Screen" coordinates are those of the drawing area as set with
+/// `Graphics2D::set_scale` (upper-left at [0, 0]), while "world"
+/// coordinates are the ones sprites are placed at.
+///
+/// The camera's position is the world point shown at the center of
+/// the viewport. Zooming and rotating happen around that point
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Camera {
+    position: Point,
+    zoom: f32,
+    rotation: f32,
+    viewport: Rect,
+}
+
+impl Camera {
+    /// Creates a camera centered on the given world point
+    /// with no zoom or rotation, drawing to the whole drawing area
+    pub fn new<P: Into<Point>>(position: P) -> Self {
+        Self {
+            position: position.into(),
+            zoom: 1.0,
+            rotation: 0.0,
+            viewport: [0.0, 0.0, 1.0, 1.0].into(),
+        }
+    }
+
+    /// A camera that leaves world and screen coordinates the same
+    pub(super) fn identity(scale: Scaling) -> Self {
+        Self::new([scale[0] / 2.0, scale[1] / 2.0])
+    }
+
+    /// The world point shown at the center of the viewport
+    pub fn position(&self) -> Point {
+        self.position
+    }
+
+    pub fn set_position<P: Into<Point>>(&mut self, position: P) {
+        self.position = position.into();
+    }
+
+    /// Moves the camera by the given amount in world coordinates
+    pub fn pan(&mut self, dx: f32, dy: f32) {
+        self.position.x += dx;
+        self.position.y += dy;
+    }
+
+    /// How much the world is magnified; 2.0 shows everything twice as large
+    pub fn zoom(&self) -> f32 {
+        self.zoom
+    }
+
+    pub fn set_zoom(&mut self, zoom: f32) {
+        self.zoom = zoom;
+    }
+
+    /// Clockwise rotation of the camera in radians.
+    /// The world appears rotated in the opposite direction
+    pub fn rotation(&self) -> f32 {
+        self.rotation
+    }
+
+    pub fn set_rotation(&mut self, rotation: f32) {
+        self.rotation = rotation;
+    }
+
+    /// The part of the drawing area the camera draws into, as fractions
+    /// of its size (between 0 and 1). Sprites outside of it are clipped
+    pub fn viewport(&self) -> Rect {
+        self.viewport
+    }
+
+    pub fn set_viewport<R: Into<Rect>>(&mut self, viewport: R) {
+        self.viewport = viewport.into();
+    }
+
+    /// Fails if the zoom can't be inverted to map screen points
+    /// back to the world
+    pub(super) fn check(&self) -> Result<()> {
+        if !(self.zoom.is_finite() && self.zoom > 0.0) {
+            err!("Camera zoom must be positive and finite, got {}", self.zoom);
+        }
+        Ok(())
+    }
+
+    /// The center of the viewport in screen coordinates
+    fn viewport_center(&self, scale: Scaling) -> [f32; 2] {
+        let [x1, y1] = self.viewport.upper_left();
+        let [x2, y2] = self.viewport.lower_right();
+        [(x1 + x2) / 2.0 * scale[0], (y1 + y2) / 2.0 * scale[1]]
+    }
+
+    /// Converts a world point to screen coordinates for a drawing
+    /// area of the given scale
+    pub(super) fn world_to_screen(&self, scale: Scaling, point: Point) -> Point {
+        let [cx, cy] = self.viewport_center(scale);
+        let (sin, cos) = self.rotation.sin_cos();
+        let dx = point.x - self.position.x;
+        let dy = point.y - self.position.y;
+        let rx = cos * dx + sin * dy;
+        let ry = -sin * dx + cos * dy;
+        [cx + self.zoom * rx, cy + self.zoom * ry].into()
+    }
+
+    /// Converts a screen point to world coordinates for a drawing
+    /// area of the given scale
+    pub(super) fn screen_to_world(&self, scale: Scaling, point: Point) -> Point {
+        let [cx, cy] = self.viewport_center(scale);
+        let (sin, cos) = self.rotation.sin_cos();
+        let rx = (point.x - cx) / self.zoom;
+        let ry = (point.y - cy) / self.zoom;
+        let dx = cos * rx - sin * ry;
+        let dy = sin * rx + cos * ry;
+        [self.position.x + dx, self.position.y + dy].into()
+    }
+
+    /// The transformation `world_to_screen` applies, as done by the
+    /// vertex shader
+    pub(super) fn transform(&self, scale: Scaling) -> Affine {
+        let [cx, cy] = self.viewport_center(scale);
+        Affine::translation(-self.position.x, -self.position.y)
+            .then(Affine::rotation(-self.rotation))
+            .then(Affine::scaling(self.zoom, self.zoom))
+            .then(Affine::translation(cx, cy))
+    }
+
+    /// The viewport in pixels of a target of the given size,
+    /// as [x, y, width, height]
+    pub(super) fn scissor_rect(&self, width: u32, height: u32) -> [u32; 4] {
+        let [x1, y1] = self.viewport.upper_left();
+        let [x2, y2] = self.viewport.lower_right();
+        let to_px = |f: f32, size: u32| (f.clamp(0.0, 1.0) * size as f32).round() as u32;
+        let (x1, x2) = (to_px(x1, width), to_px(x2, width));
+        let (y1, y2) = (to_px(y1, height), to_px(y2, height));
+        [x1, y1, x2 - x1, y2 - y1]
+    }
+
+    pub(super) fn uniform(&self, scale: Scaling) -> GlobalUniform {
+        GlobalUniform {
+            scale,
+            camera_position: self.position.to_array(),
+            viewport_center: self.viewport_center(scale),
+            zoom: self.zoom,
+            rotation: self.rotation,
+        }
+    }
+}
+
+/// Uniform data shared by all batches, laid out to match `Uniform`
+/// in shader.vert (std140)
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub(super) struct GlobalUniform {
+    scale: Scaling,
+    camera_position: [f32; 2],
+    viewport_center: [f32; 2],
+    zoom: f32,
+    rotation: f32,
+}
+
+unsafe impl bytemuck::Pod for GlobalUniform {}
+unsafe impl bytemuck::Zeroable for GlobalUniform {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn screen_to_world_inverts_world_to_screen() {
+        let scale = [320.0, 240.0];
+        let mut camera = Camera::new([12.0, -7.0]);
+        camera.set_zoom(2.5);
+        camera.set_rotation(0.7);
+        camera.set_viewport([0.25, 0.5, 0.75, 1.0]);
+        for &point in &[[0.0, 0.0], [160.0, 180.0], [-40.0, 300.0], [319.0, 1.0]] {
+            let point: Point = point.into();
+            let world = camera.screen_to_world(scale, point);
+            let screen = camera.world_to_screen(scale, world);
+            assert!(
+                (screen.x - point.x).abs() < 1e-3,
+                "{:?} != {:?}",
+                screen,
+                point
+            );
+            assert!(
+                (screen.y - point.y).abs() < 1e-3,
+                "{:?} != {:?}",
+                screen,
+                point
+            );
+        }
+    }
+
+    #[test]
+    fn zero_zoom_is_rejected() {
+        let mut graphics = Graphics2D::new_software(2, 2);
+        let mut camera = Camera::new([0.0, 0.0]);
+        camera.set_zoom(0.0);
+        assert!(graphics.set_camera(camera).is_err());
+        assert_eq!(graphics.camera(), Camera::identity(graphics.scale()));
+    }
+}
@@ -0,0 +1,34 @@
+use super::*;
+use ab_glyph::Font as _;
+
+/// A TrueType or OpenType font that text can be drawn with
+#[derive(Clone)]
+pub struct Font {
+    font: ab_glyph::FontArc,
+}
+
+impl Font {
+    /// Loads a font from the contents of a .ttf or .otf file
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self> {
+        Ok(Self {
+            font: ab_glyph::FontArc::try_from_vec(bytes)?,
+        })
+    }
+
+    /// Like `from_bytes`, but avoids copying fonts included
+    /// with `include_bytes!`
+    pub fn from_static_bytes(bytes: &'static [u8]) -> Result<Self> {
+        Ok(Self {
+            font: ab_glyph::FontArc::try_from_slice(bytes)?,
+        })
+    }
+
+    /// Returns true if the font has a glyph for the given character
+    pub fn has_char(&self, ch: char) -> bool {
+        self.font.glyph_id(ch).0 != 0
+    }
+
+    pub(super) fn inner(&self) -> &ab_glyph::FontArc {
+        &self.font
+    }
+}
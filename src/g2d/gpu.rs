@@ -0,0 +1,470 @@
+use super::*;
+
+/// Call wgpu's device.poll(..) roughly 60 times per second
+const POLL_SLEEP_DUR: Duration = Duration::from_micros((1000000.0 / 60.0) as u64);
+
+/// The wgpu objects a Graphics2D draws with when an adapter is available
+pub(super) struct Gpu {
+    pub screen: Screen,
+    pub device: Arc<wgpu::Device>,
+    pub queue: wgpu::Queue,
+    scale_uniform_bind_group_layout: wgpu::BindGroupLayout,
+    translation_uniform_bind_group_layout: wgpu::BindGroupLayout,
+    render_pipeline_layout: wgpu::PipelineLayout,
+    vs_module: wgpu::ShaderModule,
+    fs_module: wgpu::ShaderModule,
+
+    /// Layout for batches with a custom fragment shader, which
+    /// have an extra bind group for the shader's parameters
+    custom_pipeline_layout: wgpu::PipelineLayout,
+    shader_params_bind_group_layout: wgpu::BindGroupLayout,
+
+    /// Render pipelines for each combination of target format,
+    /// blend mode and custom shader that has been used
+    pub pipelines: HashMap<PipelineKey, wgpu::RenderPipeline>,
+    pub texture_bind_group_layout: wgpu::BindGroupLayout,
+
+    /// Effects applied to the frame after all batches are drawn
+    pub post: PostChain,
+
+    /// Depth buffer that lets sprites with a higher z be drawn
+    /// in front regardless of batch order.
+    /// The texture is kept so that it outlives its view
+    #[allow(dead_code)]
+    depth_texture: wgpu::Texture,
+    pub depth_view: wgpu::TextureView,
+
+    /// For reading and writing from wgpu buffers, device.poll(..) needs
+    /// to be called continuously.
+    poll_thread: Option<(std::thread::JoinHandle<()>, std::sync::mpsc::Sender<()>)>,
+}
+
+impl Gpu {
+    /// Requests a device from the first compatible adapter.
+    /// Returns None if there is no such adapter
+    pub async fn request_device(
+        compatible_surface: Option<&wgpu::Surface>,
+    ) -> Option<(wgpu::Device, wgpu::Queue)> {
+        let adapter = wgpu::Adapter::request(
+            &wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::Default,
+                compatible_surface,
+            },
+            wgpu::BackendBit::PRIMARY,
+        )
+        .await?;
+        Some(
+            adapter
+                .request_device(&wgpu::DeviceDescriptor {
+                    extensions: wgpu::Extensions {
+                        anisotropic_filtering: false,
+                    },
+                    limits: Default::default(),
+                })
+                .await,
+        )
+    }
+
+    pub fn new(device: wgpu::Device, queue: wgpu::Queue, screen: Screen) -> Result<Self> {
+        // compile shaders
+        let vs_data = wgpu::read_spirv(std::io::Cursor::new(shaders::VERT))?;
+        let fs_data = wgpu::read_spirv(std::io::Cursor::new(shaders::FRAG))?;
+        let vs_module = device.create_shader_module(&vs_data);
+        let fs_module = device.create_shader_module(&fs_data);
+
+        // sheet bind layout
+        let texture_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                bindings: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStage::FRAGMENT,
+                        ty: wgpu::BindingType::SampledTexture {
+                            multisampled: false,
+                            dimension: wgpu::TextureViewDimension::D2,
+                            component_type: wgpu::TextureComponentType::Uint,
+                        },
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStage::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler { comparison: false },
+                    },
+                ],
+                label: Some("texture_bind_group_layout"),
+            });
+
+        // scale uniform bind layout
+        let scale_uniform_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                bindings: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::VERTEX,
+                    ty: wgpu::BindingType::UniformBuffer { dynamic: false },
+                }],
+                label: Some("scale_uniform_bind_group_layout"),
+            });
+
+        // translation uniform bind layout
+        let translation_uniform_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                bindings: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::VERTEX,
+                    ty: wgpu::BindingType::UniformBuffer { dynamic: false },
+                }],
+                label: Some("translation_uniform_bind_group_layout"),
+            });
+
+        // custom fragment shader parameters bind layout
+        let shader_params_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                bindings: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::UniformBuffer { dynamic: false },
+                }],
+                label: Some("shader_params_bind_group_layout"),
+            });
+        let custom_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                bind_group_layouts: &[
+                    &texture_bind_group_layout,
+                    &scale_uniform_bind_group_layout,
+                    &translation_uniform_bind_group_layout,
+                    &shader_params_bind_group_layout,
+                ],
+            });
+
+        // pipelines are created for each blend mode and shader as needed
+        // (see Graphics2D::prepare_frame)
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                bind_group_layouts: &[
+                    &texture_bind_group_layout,
+                    &scale_uniform_bind_group_layout,
+                    &translation_uniform_bind_group_layout,
+                ],
+            });
+        let (width, height) = screen.size();
+        let (depth_texture, depth_view) = create_depth_texture(&device, width, height);
+        let post = PostChain::new(&device, &texture_bind_group_layout)?;
+
+        Ok(Self {
+            screen,
+            device: Arc::new(device),
+            queue,
+            scale_uniform_bind_group_layout,
+            translation_uniform_bind_group_layout,
+            render_pipeline_layout,
+            vs_module,
+            fs_module,
+            custom_pipeline_layout,
+            shader_params_bind_group_layout,
+            pipelines: HashMap::new(),
+            texture_bind_group_layout,
+            post,
+            depth_texture,
+            depth_view,
+            poll_thread: None,
+        })
+    }
+
+    /// Resizes the screen and the depth buffer
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.screen.resize(&self.device, width, height);
+        let (depth_texture, depth_view) = create_depth_texture(&self.device, width, height);
+        self.depth_texture = depth_texture;
+        self.depth_view = depth_view;
+    }
+
+    /// Format of the texture a frame's batches are drawn into
+    pub fn scene_format(&self) -> wgpu::TextureFormat {
+        if self.post.is_active() {
+            OFFSCREEN_FORMAT
+        } else {
+            self.screen.format()
+        }
+    }
+
+    /// Creates the pipelines needed to draw the given batches into a
+    /// texture of the given format that aren't in the cache yet
+    pub fn missing_pipelines<'a, I>(
+        &self,
+        format: wgpu::TextureFormat,
+        batches: I,
+    ) -> Vec<(PipelineKey, wgpu::RenderPipeline)>
+    where
+        I: IntoIterator<Item = &'a Batch>,
+    {
+        let mut missing: Vec<(PipelineKey, wgpu::RenderPipeline)> = vec![];
+        for batch in batches {
+            let key = batch.pipeline_key(format);
+            if !self.pipelines.contains_key(&key) && missing.iter().all(|(k, _)| *k != key) {
+                let pipeline = self.create_pipeline(format, batch.blend_mode(), batch.shader());
+                missing.push((key, pipeline));
+            }
+        }
+        missing
+    }
+
+    fn create_pipeline(
+        &self,
+        format: wgpu::TextureFormat,
+        blend_mode: BlendMode,
+        shader: Option<&Rc<Shader>>,
+    ) -> wgpu::RenderPipeline {
+        let (layout, fs_module) = match shader {
+            Some(shader) => (&self.custom_pipeline_layout, shader.module()),
+            None => (&self.render_pipeline_layout, &self.fs_module),
+        };
+        self.device
+            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                layout,
+                vertex_stage: wgpu::ProgrammableStageDescriptor {
+                    module: &self.vs_module,
+                    entry_point: "main",
+                },
+                fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
+                    module: fs_module,
+                    entry_point: "main",
+                }),
+                rasterization_state: Some(wgpu::RasterizationStateDescriptor {
+                    front_face: wgpu::FrontFace::Ccw,
+                    // A transform with a negative determinant (e.g. a
+                    // negative scale) mirrors the quad and flips its winding
+                    cull_mode: wgpu::CullMode::None,
+                    depth_bias: 0,
+                    depth_bias_slope_scale: 0.0,
+                    depth_bias_clamp: 0.0,
+                }),
+                color_states: &[blend_mode.color_state(format)],
+                primitive_topology: wgpu::PrimitiveTopology::TriangleList,
+                // LessEqual keeps the painter's order for sprites with equal z
+                depth_stencil_state: Some(wgpu::DepthStencilStateDescriptor {
+                    format: DEPTH_FORMAT,
+                    depth_write_enabled: blend_mode.writes_depth(),
+                    depth_compare: wgpu::CompareFunction::LessEqual,
+                    stencil_front: wgpu::StencilStateFaceDescriptor::IGNORE,
+                    stencil_back: wgpu::StencilStateFaceDescriptor::IGNORE,
+                    stencil_read_mask: 0,
+                    stencil_write_mask: 0,
+                }),
+                vertex_state: wgpu::VertexStateDescriptor {
+                    index_format: wgpu::IndexFormat::Uint16,
+                    vertex_buffers: &[Instance::desc()],
+                },
+                sample_count: 1,
+                sample_mask: !0,
+                alpha_to_coverage_enabled: false,
+            })
+    }
+
+    /// Records the commands for drawing the given batches in order
+    /// (i.e. later batches are drawn over earlier ones) into a target.
+    /// The flag paired with each batch tells whether it's viewed
+    /// through the target's camera
+    pub fn encode_batches(
+        &self,
+        target: &PassTarget,
+        batches: &[(&Batch, bool)],
+    ) -> wgpu::CommandBuffer {
+        struct BatchInfo<'a> {
+            batch: &'a Batch,
+            instance_buffer: &'a wgpu::Buffer,
+            translation_bind_group: wgpu::BindGroup,
+            shader_params_bind_group: Option<wgpu::BindGroup>,
+            instance_len: usize,
+            follows_camera: bool,
+        }
+        let batches_with_instance_buffers = {
+            let mut vec = Vec::new();
+            for &(batch, follows_camera) in batches {
+                let (instance_buffer, instance_len) = batch.instance_buffer();
+                let translation_buffer = self.device.create_buffer_with_data(
+                    bytemuck::cast_slice(&[batch.uniform()]),
+                    wgpu::BufferUsage::UNIFORM,
+                );
+                let translation_bind_group =
+                    self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                        layout: &self.translation_uniform_bind_group_layout,
+                        bindings: &[wgpu::Binding {
+                            binding: 0,
+                            resource: wgpu::BindingResource::Buffer {
+                                buffer: &translation_buffer,
+                                range: 0..std::mem::size_of::<BatchUniform>()
+                                    as wgpu::BufferAddress,
+                            },
+                        }],
+                        label: Some("per_batch_scale_uniform_bind_group"),
+                    });
+                let shader_params_bind_group = match batch.shader() {
+                    Some(_) => {
+                        // Uniform buffers can't be empty, and are padded
+                        // to a whole vec4 to be safe
+                        let mut params = batch.shader_params().to_vec();
+                        let padded_len = params.len().div_ceil(4).max(1) * 4;
+                        params.resize(padded_len, 0.0);
+                        let params_buffer = self.device.create_buffer_with_data(
+                            bytemuck::cast_slice(&params),
+                            wgpu::BufferUsage::UNIFORM,
+                        );
+                        Some(self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                            layout: &self.shader_params_bind_group_layout,
+                            bindings: &[wgpu::Binding {
+                                binding: 0,
+                                resource: wgpu::BindingResource::Buffer {
+                                    buffer: &params_buffer,
+                                    range: 0..(params.len() * std::mem::size_of::<f32>())
+                                        as wgpu::BufferAddress,
+                                },
+                            }],
+                            label: Some("shader_params_bind_group"),
+                        }))
+                    }
+                    None => None,
+                };
+                vec.push(BatchInfo {
+                    batch,
+                    instance_buffer,
+                    translation_bind_group,
+                    shader_params_bind_group,
+                    instance_len,
+                    follows_camera,
+                });
+            }
+            vec
+        };
+        let create_global_bind_group = |camera: Camera| {
+            let buffer = self.device.create_buffer_with_data(
+                bytemuck::cast_slice(&[camera.uniform(target.scale)]),
+                wgpu::BufferUsage::UNIFORM,
+            );
+            self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: &self.scale_uniform_bind_group_layout,
+                bindings: &[wgpu::Binding {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Buffer {
+                        buffer: &buffer,
+                        range: 0..std::mem::size_of::<GlobalUniform>() as wgpu::BufferAddress,
+                    },
+                }],
+                label: Some("global_uniform_bind_group"),
+            })
+        };
+        let camera = target.camera;
+        let screen_bind_group = create_global_bind_group(Camera::identity(target.scale));
+        let camera_bind_group = create_global_bind_group(camera);
+        let (target_width, target_height) = target.size;
+        let [sx, sy, sw, sh] = camera.scissor_rect(target_width, target_height);
+        let (load_op, clear_color) = match target.clear_color {
+            Some(color) => {
+                let (r, g, b, a) = color.unpack();
+                let clear_color = wgpu::Color {
+                    r: r as f64,
+                    g: g as f64,
+                    b: b as f64,
+                    a: a as f64,
+                };
+                (wgpu::LoadOp::Clear, clear_color)
+            }
+            None => {
+                let clear_color = wgpu::Color {
+                    r: 0.0,
+                    g: 0.0,
+                    b: 0.0,
+                    a: 0.0,
+                };
+                (wgpu::LoadOp::Load, clear_color)
+            }
+        };
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Render Encoder"),
+            });
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+                    attachment: target.view,
+                    resolve_target: None,
+                    load_op,
+                    store_op: wgpu::StoreOp::Store,
+                    clear_color,
+                }],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachmentDescriptor {
+                    attachment: target.depth_view,
+                    depth_load_op: wgpu::LoadOp::Clear,
+                    depth_store_op: wgpu::StoreOp::Store,
+                    clear_depth: 1.0,
+                    stencil_load_op: wgpu::LoadOp::Clear,
+                    stencil_store_op: wgpu::StoreOp::Store,
+                    clear_stencil: 0,
+                }),
+            });
+            for info in &batches_with_instance_buffers {
+                let batch = info.batch;
+                let instance_buffer = &info.instance_buffer;
+                let translation_bind_group = &info.translation_bind_group;
+                let instance_len = info.instance_len;
+                if info.follows_camera {
+                    if sw == 0 || sh == 0 {
+                        continue;
+                    }
+                    render_pass.set_scissor_rect(sx, sy, sw, sh);
+                    render_pass.set_bind_group(1, &camera_bind_group, &[]);
+                } else {
+                    render_pass.set_scissor_rect(0, 0, target_width, target_height);
+                    render_pass.set_bind_group(1, &screen_bind_group, &[]);
+                }
+                render_pass.set_pipeline(&self.pipelines[&batch.pipeline_key(target.format)]);
+                if let Some(shader_params_bind_group) = &info.shader_params_bind_group {
+                    render_pass.set_bind_group(3, shader_params_bind_group, &[]);
+                }
+                render_pass.set_bind_group(0, batch.sheet().bind_group(), &[]);
+                render_pass.set_bind_group(2, translation_bind_group, &[]);
+                render_pass.set_vertex_buffer(0, instance_buffer, 0, 0);
+                render_pass.draw(0..6, 0..instance_len as u32);
+            }
+        }
+
+        encoder.finish()
+    }
+
+    /// Records the copies needed to bring the instance buffers of
+    /// the given batches up to date, and submits them
+    pub fn flush_batches<'a, I>(&self, batches: I)
+    where
+        I: IntoIterator<Item = &'a mut Batch>,
+    {
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("batch_flush_encoder"),
+            });
+        for batch in batches {
+            batch.flush(&self.device, &mut encoder);
+        }
+        self.queue.submit(&[encoder.finish()]);
+    }
+
+    pub fn ensure_polling(&mut self) -> Result<()> {
+        if self.poll_thread.is_none() {
+            let device = self.device.clone();
+            let (sender, receiver) = std::sync::mpsc::channel();
+            let thread = std::thread::Builder::new()
+                .name("a2d-wgpu-poll".to_owned())
+                .spawn(move || loop {
+                    match receiver.try_recv() {
+                        Ok(()) | Err(std::sync::mpsc::TryRecvError::Disconnected) => break,
+                        Err(std::sync::mpsc::TryRecvError::Empty) => {}
+                    }
+                    device.poll(wgpu::Maintain::Wait);
+                    std::thread::sleep(POLL_SLEEP_DUR);
+                    std::thread::yield_now();
+                })?;
+            self.poll_thread = Some((thread, sender));
+        }
+        Ok(())
+    }
+}
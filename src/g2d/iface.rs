@@ -2,17 +2,53 @@ use super::*;
 
 /// Public methods of Graphics2D
 impl Graphics2D {
+    /// Creates a Graphics2D that renders into the given window.
+    ///
+    /// If no compatible wgpu adapter is found, this falls back to the
+    /// software renderer (see `new_software`). Frames are then not
+    /// presented in the window, but can still be read with `screenshot`
     pub async fn new<W: HasRawWindowHandle>(width: u32, height: u32, window: &W) -> Result<Self> {
         let mut graphics = Self::new0(width, height, window).await?;
         graphics.set_scale([width as f32, height as f32]);
         Ok(graphics)
     }
 
+    /// Creates a Graphics2D that renders into an offscreen texture of the
+    /// given size instead of a window.
+    ///
+    /// The rendered image can be retrieved with `read_pixels`.
+    /// If no compatible wgpu adapter is found, this falls back to the
+    /// software renderer (see `new_software`)
+    pub async fn new_offscreen(width: u32, height: u32) -> Result<Self> {
+        let mut graphics = Self::new_offscreen0(width, height).await?;
+        graphics.set_scale([width as f32, height as f32]);
+        Ok(graphics)
+    }
+
+    /// Creates a Graphics2D that draws on the CPU into an offscreen
+    /// frame of the given size, without needing a GPU.
+    ///
+    /// Batches are drawn the same way as on the GPU, except that custom
+    /// shaders, post-processing effects and render targets are not
+    /// supported, and mipmaps are not used when sampling sheets
+    pub fn new_software(width: u32, height: u32) -> Self {
+        let mut graphics = Self::new_software0(width, height);
+        graphics.set_scale([width as f32, height as f32]);
+        graphics
+    }
+
+    /// Whether this Graphics2D draws with the software renderer,
+    /// either because it was created with `new_software` or because
+    /// no wgpu adapter was found
+    pub fn is_software(&self) -> bool {
+        self.renderer.gpu().is_none()
+    }
+
     /// Flushes all pending writes to their buffers
     /// This needs to be called for render to show updates
     pub fn flush(&mut self) -> Result<()> {
-        self.ensure_polling()?;
-        futures::executor::block_on(self.async_flush())?;
+        self.rebuild_free_text();
+        self.flush_batches();
         self.dirty = true;
         Ok(())
     }
@@ -26,107 +62,188 @@ impl Graphics2D {
 
     pub fn force_render(&mut self) -> Result<()> {
         self.dirty = false;
-        struct BatchInfo<'a> {
-            batch: &'a Batch,
-            instance_buffer: &'a wgpu::Buffer,
-            translation_bind_group: wgpu::BindGroup,
-            instance_len: usize,
-        }
-        let batches_with_instance_buffers = {
-            let mut vec = Vec::new();
-            for batch in self.batches.iter().rev().flatten() {
-                let instance_buffer = batch.instance_buffer();
-                let instance_len = batch.len();
-                let translation_buffer = self.device.create_buffer_with_data(
-                    bytemuck::cast_slice(&[batch.scale(), batch.translation()]),
-                    wgpu::BufferUsage::UNIFORM,
-                );
-                let translation_bind_group =
-                    self.device.create_bind_group(&wgpu::BindGroupDescriptor {
-                        layout: &self.translation_uniform_bind_group_layout,
-                        bindings: &[wgpu::Binding {
-                            binding: 0,
-                            resource: wgpu::BindingResource::Buffer {
-                                buffer: &translation_buffer,
-                                range: 0..(std::mem::size_of::<Scaling>()
-                                    + std::mem::size_of::<Translation>())
-                                    as wgpu::BufferAddress,
-                            },
-                        }],
-                        label: Some("per_batch_scale_uniform_bind_group"),
-                    });
-                vec.push(BatchInfo {
-                    batch,
-                    instance_buffer,
-                    translation_bind_group,
-                    instance_len,
-                });
+        self.prepare_frame()?;
+        let frame = match &mut self.renderer {
+            Renderer::Gpu(gpu) => gpu.screen.next_frame(),
+            Renderer::Soft { .. } => {
+                self.render_soft();
+                return Ok(());
             }
-            vec
         };
-        let scale_uniform_bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &self.scale_uniform_bind_group_layout,
-            bindings: &[wgpu::Binding {
-                binding: 0,
-                resource: wgpu::BindingResource::Buffer {
-                    buffer: &self.scale_uniform_buffer,
-                    range: 0..std::mem::size_of::<Scaling>() as wgpu::BufferAddress,
-                },
-            }],
-            label: Some("default_scale_uniform_bind_group"),
-        });
-        let frame = self
-            .swap_chain
-            .get_next_texture()
-            .expect("Timeout getting next texture");
-        let mut encoder = self
-            .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                label: Some("Render Encoder"),
-            });
-        {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
-                    attachment: &frame.view,
-                    resolve_target: None,
-                    load_op: wgpu::LoadOp::Clear,
-                    store_op: wgpu::StoreOp::Store,
-                    clear_color: wgpu::Color {
-                        r: 0.0,
-                        g: 0.0,
-                        b: 0.0,
-                        a: 0.0,
-                    },
-                }],
-                depth_stencil_attachment: None,
-            });
-            render_pass.set_pipeline(&self.render_pipeline);
-            for info in &batches_with_instance_buffers {
-                let batch = info.batch;
-                let instance_buffer = &info.instance_buffer;
-                let translation_bind_group = &info.translation_bind_group;
-                let instance_len = info.instance_len;
-                render_pass.set_bind_group(0, batch.sheet().bind_group(), &[]);
-                render_pass.set_bind_group(1, &scale_uniform_bind_group, &[]);
-                render_pass.set_bind_group(2, translation_bind_group, &[]);
-                render_pass.set_vertex_buffer(0, instance_buffer, 0, 0);
-                render_pass.draw(0..6, 0..instance_len as u32);
-            }
+        if let Renderer::Gpu(gpu) = &self.renderer {
+            let commands = self.encode_frame(gpu, gpu.screen.view(&frame), self.clear_color);
+            gpu.queue.submit(&commands);
         }
-
-        self.queue.submit(&[encoder.finish()]);
         Ok(())
     }
 
     /// Call this method to notify A2D that the window has been resized
     pub fn resized(&mut self, width: u32, height: u32) {
-        self.sc_desc.width = width;
-        self.sc_desc.height = height;
-        self.swap_chain = self.device.create_swap_chain(&self.surface, &self.sc_desc);
+        match &mut self.renderer {
+            Renderer::Gpu(gpu) => gpu.resize(width, height),
+            Renderer::Soft {
+                width: frame_width,
+                height: frame_height,
+                frame,
+            } => {
+                *frame_width = width;
+                *frame_height = height;
+                *frame = vec![0; (width * height * 4) as usize];
+            }
+        }
         self.set_scale([width as f32, height as f32]);
         self.text_grid_dim = None;
     }
 
+    /// Captures the current frame as an image.
+    ///
+    /// For a Graphics2D created with `new_offscreen`, this is the most
+    /// recently rendered frame. The contents of a window's swap chain cannot
+    /// be read back, so for a window the batches are rendered again into
+    /// a texture of the same size (pending updates that haven't been
+    /// flushed will not show up in either case). Since the previous
+    /// frames can't be read back either, a window's screenshot after
+    /// `retain_previous_frame` only shows the batches drawn over
+    /// transparent black.
+    ///
+    /// With the software renderer, this is the most recently rendered frame.
+    pub fn screenshot(&mut self) -> Result<Screenshot> {
+        self.ensure_polling()?;
+        self.prepare_frame()?;
+        let gpu = match &self.renderer {
+            Renderer::Gpu(gpu) => gpu,
+            Renderer::Soft {
+                width,
+                height,
+                frame,
+            } => return Ok(Screenshot::new(*width, *height, frame.clone())),
+        };
+        let (width, height) = gpu.screen.size();
+        let mut rgba = match &gpu.screen {
+            Screen::Offscreen { texture, .. } => futures::executor::block_on(read_texture(
+                &gpu.device,
+                &gpu.queue,
+                texture,
+                width,
+                height,
+            ))?,
+            Screen::Window { .. } => {
+                let texture = create_capture_texture(&gpu.device, &gpu.screen);
+                // The capture texture starts out undefined, so it's always
+                // cleared even if the previous frame is being retained
+                let clear_color = self
+                    .clear_color
+                    .or_else(|| Some([0.0, 0.0, 0.0, 0.0].into()));
+                let commands = self.encode_frame(gpu, &texture.create_default_view(), clear_color);
+                gpu.queue.submit(&commands);
+                futures::executor::block_on(read_texture(
+                    &gpu.device,
+                    &gpu.queue,
+                    &texture,
+                    width,
+                    height,
+                ))?
+            }
+        };
+        if gpu.screen.format() == wgpu::TextureFormat::Bgra8UnormSrgb {
+            for pixel in rgba.chunks_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+        Ok(Screenshot::new(width, height, rgba))
+    }
+
+    /// Returns the RGBA bytes of the current frame,
+    /// 4 bytes per pixel, row by row starting from the upper-left corner.
+    ///
+    /// See `screenshot` for more info.
+    pub fn read_pixels(&mut self) -> Result<Vec<u8>> {
+        Ok(self.screenshot()?.into_rgba())
+    }
+
+    /// Draws the given batches into a render target, flushing their
+    /// pending updates first.
+    ///
+    /// Like slots, the first batch is drawn on top of all the others.
+    /// Batches that follow the camera are viewed through the target's camera.
+    ///
+    /// Returns an error if a batch uses the target's own sheet
+    pub fn render_to_target(
+        &mut self,
+        target: &RenderTarget,
+        batches: &mut [&mut Batch],
+    ) -> Result<()> {
+        if batches
+            .iter()
+            .any(|batch| Rc::ptr_eq(batch.sheet(), target.sheet()))
+        {
+            err!("A batch cannot be drawn into the render target whose sheet it uses");
+        }
+        let gpu = match self.renderer.gpu_mut() {
+            Some(gpu) => gpu,
+            None => {
+                err!("Render targets are not supported by the software renderer");
+            }
+        };
+        gpu.flush_batches(batches.iter_mut().map(|batch| &mut **batch));
+        let batches: Vec<_> = batches
+            .iter()
+            .rev()
+            .map(|batch| (&**batch, batch.follows_camera()))
+            .collect();
+        let missing = gpu.missing_pipelines(OFFSCREEN_FORMAT, batches.iter().map(|b| b.0));
+        gpu.pipelines.extend(missing);
+        let commands = gpu.encode_batches(&target.pass_target(), &batches);
+        gpu.queue.submit(&[commands]);
+        Ok(())
+    }
+
+    /// Draws the batches in the given slots into a render target,
+    /// e.g. to show the same world in a minimap through another camera.
+    ///
+    /// Like `force_render`, the batches are drawn as last flushed and
+    /// the first slot is drawn on top. Empty slots are skipped.
+    /// Returns an error if a batch uses the target's own sheet
+    pub fn render_slots_to_target(&mut self, target: &RenderTarget, slots: &[usize]) -> Result<()> {
+        if let Some(&slot) = slots.iter().find(|&&slot| slot >= SLOT_LIMIT) {
+            err!(
+                "Slot {} is out of bounds (SLOT_LIMIT = {})",
+                slot,
+                SLOT_LIMIT
+            );
+        }
+        if let Some(&slot) = slots.iter().find(|&&slot| match &self.batches[slot] {
+            Some(batch) => Rc::ptr_eq(batch.sheet(), target.sheet()),
+            None => false,
+        }) {
+            err!(
+                "The batch in slot {} cannot be drawn into the render target whose sheet it uses",
+                slot
+            );
+        }
+        let slot_batches = &self.batches;
+        let gpu = match &mut self.renderer {
+            Renderer::Gpu(gpu) => gpu,
+            Renderer::Soft { .. } => {
+                err!("Render targets are not supported by the software renderer");
+            }
+        };
+        let missing = gpu.missing_pipelines(
+            OFFSCREEN_FORMAT,
+            slots.iter().filter_map(|&slot| slot_batches[slot].as_ref()),
+        );
+        gpu.pipelines.extend(missing);
+        let batches: Vec<_> = slots
+            .iter()
+            .rev()
+            .filter_map(|&slot| slot_batches[slot].as_ref())
+            .map(|batch| (batch, batch.follows_camera()))
+            .collect();
+        let commands = gpu.encode_batches(&target.pass_target(), &batches);
+        gpu.queue.submit(&[commands]);
+        Ok(())
+    }
+
     /// By default, the screen coordinates are [0, 0] for the
     /// upper-left corner and [width, height] for the lower-right corner.
     /// The coordinates of the lower-right corner may be customized
@@ -141,10 +258,133 @@ impl Graphics2D {
     /// See the method `scale` for more info.
     pub fn set_scale(&mut self, new_scale: [f32; 2]) {
         self.scale = new_scale;
-        self.scale_uniform_buffer = self.device.create_buffer_with_data(
-            bytemuck::cast_slice(&self.scale),
-            wgpu::BufferUsage::UNIFORM,
+        if let Some(background) = &mut self.background {
+            background.set_scale(new_scale);
+        }
+        self.dirty = true;
+    }
+
+    /// The camera that user batches are viewed through.
+    /// Defaults to one that leaves world and screen coordinates the same
+    pub fn camera(&self) -> Camera {
+        match self.camera {
+            Some(camera) => camera,
+            None => Camera::identity(self.scale),
+        }
+    }
+
+    /// Sets the camera that user batches are viewed through.
+    ///
+    /// The builtin text, pixel and background batches, and batches
+    /// that have `set_follows_camera(false)`, are drawn in screen
+    /// coordinates regardless of the camera.
+    /// Fails if the camera's zoom isn't positive
+    pub fn set_camera(&mut self, camera: Camera) -> Result<()> {
+        camera.check()?;
+        self.camera = Some(camera);
+        self.dirty = true;
+        Ok(())
+    }
+
+    /// Goes back to the default camera (see `camera`)
+    pub fn reset_camera(&mut self) {
+        self.camera = None;
+        self.dirty = true;
+    }
+
+    /// Converts a point in screen coordinates (i.e. the coordinates set
+    /// with `set_scale`) to the world point the camera shows there.
+    /// Useful for finding what's under the mouse cursor
+    pub fn screen_to_world<P: Into<Point>>(&self, point: P) -> Point {
+        self.camera().screen_to_world(self.scale, point.into())
+    }
+
+    /// Converts a world point to where the camera shows it
+    /// in screen coordinates
+    pub fn world_to_screen<P: Into<Point>>(&self, point: P) -> Point {
+        self.camera().world_to_screen(self.scale, point.into())
+    }
+
+    /// The color the frame is cleared to before any batches are drawn,
+    /// or None if the previous frame is kept.
+    /// Defaults to transparent black.
+    pub fn clear_color(&self) -> Option<Color> {
+        self.clear_color
+    }
+
+    /// Sets the color the frame is cleared to before any batches are drawn
+    pub fn set_clear_color<C: Into<Color>>(&mut self, color: C) {
+        self.clear_color = Some(color.into());
+        self.dirty = true;
+    }
+
+    /// Stops clearing the frame before drawing, so that batches are
+    /// drawn over whatever was rendered previously.
+    ///
+    /// This works as expected with `new_offscreen`. With a window, the
+    /// swap chain may cycle through several textures, so the previous
+    /// contents seen may be from an older frame.
+    pub fn retain_previous_frame(&mut self) {
+        self.clear_color = None;
+        self.dirty = true;
+    }
+
+    /// Sets an image to be stretched over the whole drawing area
+    /// underneath all batches
+    pub fn set_background(&mut self, sheet: Rc<Sheet>) {
+        let mut background = Batch::new(
+            self,
+            sheet,
+            1,
+            1,
+            &[SpriteDesc {
+                // Behind every other sprite regardless of their z
+                z: -1.0,
+                ..SpriteDesc::new(0, [0.0, 0.0, 1.0, 1.0])
+            }],
         );
+        background.set_scale(self.scale);
+        self.background = Some(background);
+        self.dirty = true;
+    }
+
+    /// Removes the background image set with `set_background`
+    pub fn clear_background(&mut self) {
+        self.background = None;
+        self.dirty = true;
+    }
+
+    /// The effects applied to the frame after all batches are drawn
+    pub fn post_effects(&self) -> &[PostEffect] {
+        match self.renderer.gpu() {
+            Some(gpu) => gpu.post.effects(),
+            None => &[],
+        }
+    }
+
+    /// Sets the effects applied, in order, to the frame after all
+    /// batches are drawn. The batches are then drawn into an intermediate
+    /// texture instead of directly into the window or offscreen texture.
+    ///
+    /// Fails if a `PostEffect::ColorGrade` table has the wrong size,
+    /// or with the software renderer, which doesn't support effects
+    pub fn set_post_effects(&mut self, effects: Vec<PostEffect>) -> Result<()> {
+        match self.renderer.gpu_mut() {
+            Some(gpu) => gpu.post.set_effects(effects)?,
+            None => {
+                err!("Post-processing effects are not supported by the software renderer");
+            }
+        }
+        self.dirty = true;
+        Ok(())
+    }
+
+    /// Removes all post-processing effects
+    pub fn clear_post_effects(&mut self) {
+        if let Some(gpu) = self.renderer.gpu_mut() {
+            gpu.post.clear_effects();
+        }
+        self.dirty = true;
     }
 
     /// Returns the number of sprites the batch at the given slot has.
@@ -154,6 +394,39 @@ impl Graphics2D {
         self.batches[slot].as_ref().unwrap().len()
     }
 
+    /// Places a batch in the given slot, replacing any batch that
+    /// was already there.
+    ///
+    /// Batches in lower numbered slots are drawn on top of batches
+    /// in higher numbered slots. The builtin text and pixel batches
+    /// don't use slots, and are drawn on top of all of them
+    /// regardless of z.
+    pub fn set_batch(&mut self, slot: usize, batch: Batch) -> Result<()> {
+        self.check_user_slot(slot)?;
+        self.batches[slot] = Some(batch);
+        self.dirty = true;
+        Ok(())
+    }
+
+    /// Returns the batch in the given slot, if any
+    pub fn batch(&self, slot: usize) -> Option<&Batch> {
+        self.batches.get(slot).and_then(|b| b.as_ref())
+    }
+
+    /// Returns the batch in the given slot, if any, so that its
+    /// sprites may be updated
+    pub fn batch_mut(&mut self, slot: usize) -> Option<&mut Batch> {
+        self.dirty = true;
+        self.batches.get_mut(slot).and_then(|b| b.as_mut())
+    }
+
+    /// Removes and returns the batch in the given slot
+    pub fn remove_batch(&mut self, slot: usize) -> Result<Option<Batch>> {
+        self.check_user_slot(slot)?;
+        self.dirty = true;
+        Ok(self.batches[slot].take())
+    }
+
     /// Uses the builtin pixel batch to draw a pixel of the given color at the
     /// given location
     ///
@@ -183,45 +456,111 @@ impl Graphics2D {
         let [width, height] = self.scale();
         let step_width = width / (ncols + 1) as f32;
         let step_height = res::CHAR_HEIGHT_TO_WIDTH_RATIO * step_width;
-        let dest_width = step_width * 2.0;
-        let dest_height = step_height * 2.0;
         let nrows = (height / step_height) as usize;
-        let mut descs = vec![];
-        for row in 0..nrows {
-            let y = step_height * (row as f32);
-            for col in 0..ncols {
-                let x = step_width * (col as f32);
-                descs.push(SpriteDesc {
-                    color: [1.0, 1.0, 1.0].into(),
-                    src: res::CHAR_EMPTY_SPACE_INDEX,
-                    dst: [x, y, x + dest_width, y + dest_height].into(),
-                    rotate: 0.0,
-                });
-            }
-        }
+        // The courier glyphs only fill the middle of their cells in the
+        // charmap, so they are drawn at twice the size of a grid step
+        let (descs, background_descs) = text_grid_descs(
+            nrows,
+            ncols,
+            step_width,
+            step_height,
+            2.0,
+            res::CHAR_EMPTY_SPACE_INDEX,
+        );
         let sheet = Sheet::from_bytes(self, res::COURIER_CHARMAP)?;
-        self.batches[BATCH_SLOT_TEXT] = Some(Batch::new(
+        self.text_atlas = None;
+        self.builtin_batches[BUILTIN_TEXT] = Some(Batch::new(
             self,
             sheet,
             res::CHARMAP_NROWS,
             res::CHARMAP_NCOLS,
             &descs,
         ));
+        self.init_text_background(&background_descs)?;
+        let dim = TextGridDim { nrows, ncols };
+        self.text_grid_dim = Some(dim);
+        Ok(dim)
+    }
+
+    /// Like `init_text_grid`, but draws characters with the given font
+    /// instead of the builtin courier charmap.
+    ///
+    /// Glyphs are rasterized on demand the first time each character
+    /// is drawn. Characters the font does not have are drawn as a box.
+    pub fn init_text_grid_with_font(&mut self, ncols: usize, font: &Font) -> Result<TextGridDim> {
+        let atlas = GlyphAtlas::new(self, font, TEXT_GRID_FONT_PX_HEIGHT, true)?;
+        let [width, height] = self.scale();
+        let step_width = width / ncols as f32;
+        let step_height = step_width * atlas.cell_aspect_ratio();
+        let nrows = (height / step_height) as usize;
+        let (descs, background_descs) = text_grid_descs(
+            nrows,
+            ncols,
+            step_width,
+            step_height,
+            1.0,
+            atlas.empty_index(),
+        );
+        let sheet = atlas.sheet().clone();
+        let (atlas_nrows, atlas_ncols) = (atlas.nrows(), atlas.ncols());
+        self.text_atlas = Some(atlas);
+        self.builtin_batches[BUILTIN_TEXT] =
+            Some(Batch::new(self, sheet, atlas_nrows, atlas_ncols, &descs));
+        self.init_text_background(&background_descs)?;
         let dim = TextGridDim { nrows, ncols };
         self.text_grid_dim = Some(dim);
         Ok(dim)
     }
 
     pub fn draw_char(&mut self, row: usize, col: usize, ch: char) -> Result<()> {
-        if self.text_grid_dim.is_none() {
-            self.init_text_grid(DEFAULT_TEXT_NCOLS)?;
-        }
-        let TextGridDim { nrows, ncols } = self.text_grid_dim.unwrap();
-        if row < nrows && col < ncols {
-            let instance_index = ncols * row + col;
-            if let Some(src) = res::char_to_charmap_index(ch) {
-                self.text_batch()?.get(instance_index).src(src);
-            }
+        if let Some(instance_index) = self.text_cell_index(row, col)? {
+            let src = self.text_src_index(ch);
+            self.text_batch()?.get(instance_index).src(src);
+        }
+        Ok(())
+    }
+
+    /// Like `draw_char`, but also sets the color of the glyph and
+    /// the color of the cell behind it
+    pub fn draw_char_styled<C1, C2>(
+        &mut self,
+        row: usize,
+        col: usize,
+        ch: char,
+        fg: C1,
+        bg: C2,
+    ) -> Result<()>
+    where
+        C1: Into<Color>,
+        C2: Into<Color>,
+    {
+        if let Some(instance_index) = self.text_cell_index(row, col)? {
+            let src = self.text_src_index(ch);
+            self.text_batch()?.get(instance_index).src(src).color(fg);
+            self.text_background_batch()?.get(instance_index).color(bg);
+        }
+        Ok(())
+    }
+
+    /// Sets the color of the glyph in the given cell without
+    /// changing the character
+    pub fn set_char_color<C: Into<Color>>(&mut self, row: usize, col: usize, fg: C) -> Result<()> {
+        if let Some(instance_index) = self.text_cell_index(row, col)? {
+            self.text_batch()?.get(instance_index).color(fg);
+        }
+        Ok(())
+    }
+
+    /// Sets the background color of the given cell.
+    /// Backgrounds are transparent by default
+    pub fn set_char_background<C: Into<Color>>(
+        &mut self,
+        row: usize,
+        col: usize,
+        bg: C,
+    ) -> Result<()> {
+        if let Some(instance_index) = self.text_cell_index(row, col)? {
+            self.text_background_batch()?.get(instance_index).color(bg);
         }
         Ok(())
     }
@@ -233,6 +572,139 @@ impl Graphics2D {
         }
         Ok(())
     }
+
+    /// Like `draw_text`, but also sets the glyph and background
+    /// colors of every cell drawn
+    pub fn draw_text_styled<C1, C2>(
+        &mut self,
+        row: usize,
+        col: usize,
+        text: &str,
+        fg: C1,
+        bg: C2,
+    ) -> Result<()>
+    where
+        C1: Into<Color>,
+        C2: Into<Color>,
+    {
+        let fg = fg.into();
+        let bg = bg.into();
+        for (i, ch) in text.chars().enumerate() {
+            self.draw_char_styled(row, col + i, ch, fg, bg)?;
+        }
+        Ok(())
+    }
+
+    /// Sets the font used by `draw_text_at`.
+    /// Without a font, the builtin courier charmap is used.
+    ///
+    /// Any text already drawn with `draw_text_at` is cleared.
+    pub fn set_text_font(&mut self, font: &Font) -> Result<()> {
+        let atlas = GlyphAtlas::new(self, font, FREE_TEXT_FONT_PX_HEIGHT, false)?;
+        let sheet = atlas.sheet().clone();
+        self.free_text = Some(FreeText::new(TextFace::Font(atlas), sheet));
+        Ok(())
+    }
+
+    /// Draws a line of text with its upper-left corner at the given point.
+    ///
+    /// Unlike `draw_text`, the text is not confined to the text grid.
+    /// `size` is the height of a line of text.
+    /// The text stays on screen until `clear_text` is called.
+    pub fn draw_text_at<P, C>(&mut self, point: P, text: &str, size: f32, color: C) -> Result<()>
+    where
+        P: Into<Point>,
+        C: Into<Color>,
+    {
+        self.draw_text_with_options(point, text, &TextOptions::new(size).color(color))
+    }
+
+    /// Like `draw_text_at`, but with control over wrapping and alignment.
+    ///
+    /// The point is the upper-left corner of the block of text. Lines are
+    /// aligned within `options.max_width` if set, and otherwise within
+    /// the width of the widest line.
+    pub fn draw_text_with_options<P: Into<Point>>(
+        &mut self,
+        point: P,
+        text: &str,
+        options: &TextOptions,
+    ) -> Result<()> {
+        let point = point.into();
+        self.free_text()?;
+        let free_text = self.free_text.as_mut().unwrap();
+        free_text.draw(self.renderer.gpu(), point, text, options);
+        Ok(())
+    }
+
+    /// Returns the size of the block of text `draw_text_with_options`
+    /// would draw with the same arguments
+    pub fn measure_text(&mut self, text: &str, options: &TextOptions) -> Result<Dimensions> {
+        Ok(self.free_text()?.measure(text, options))
+    }
+
+    /// Removes all text drawn with `draw_text_at` and `draw_text_with_options`
+    pub fn clear_text(&mut self) {
+        if let Some(free_text) = &mut self.free_text {
+            free_text.clear();
+        }
+    }
+}
+
+/// Computes the glyph and background sprites for a text grid.
+/// Glyphs are drawn `glyph_scale` times the size of a grid step
+fn text_grid_descs(
+    nrows: usize,
+    ncols: usize,
+    step_width: f32,
+    step_height: f32,
+    glyph_scale: f32,
+    empty_src: usize,
+) -> (Vec<SpriteDesc>, Vec<SpriteDesc>) {
+    let dest_width = step_width * glyph_scale;
+    let dest_height = step_height * glyph_scale;
+    // The background cell is centered on the glyph
+    let bg_dx = step_width * (glyph_scale - 1.0) / 2.0;
+    let bg_dy = step_height * (glyph_scale - 1.0) / 2.0;
+    let mut descs = vec![];
+    let mut background_descs = vec![];
+    for row in 0..nrows {
+        let y = step_height * (row as f32);
+        for col in 0..ncols {
+            let x = step_width * (col as f32);
+            descs.push(SpriteDesc {
+                src_rect: None,
+                flip: [false, false],
+                z: BUILTIN_Z,
+                visible: true,
+                transform: Affine::IDENTITY,
+                tiling: None,
+                color: [1.0, 1.0, 1.0].into(),
+                src: empty_src,
+                dst: [x, y, x + dest_width, y + dest_height].into(),
+                rotate: 0.0,
+            });
+            background_descs.push(SpriteDesc {
+                src_rect: None,
+                flip: [false, false],
+                z: BUILTIN_Z,
+                visible: true,
+                transform: Affine::IDENTITY,
+                tiling: None,
+                color: [0.0, 0.0, 0.0, 0.0].into(),
+                src: 0,
+                dst: [
+                    x + bg_dx,
+                    y + bg_dy,
+                    x + bg_dx + step_width,
+                    y + bg_dy + step_height,
+                ]
+                .into(),
+                rotate: 0.0,
+            });
+        }
+    }
+    (descs, background_descs)
 }
 
 #[derive(Clone, Copy)]
@@ -1,8 +1,5 @@
 use super::*;
 
-/// Call wgpu's device.poll(..) roughly 60 times per second
-const POLL_SLEEP_DUR: Duration = Duration::from_micros((1000000.0 / 60.0) as u64);
-
 /// Helper methods on Graphics2D (all listed here should be private to a2d)
 impl Graphics2D {
     pub(super) async fn new0<W: HasRawWindowHandle>(
@@ -11,156 +8,155 @@ impl Graphics2D {
         window: &W,
     ) -> Result<Self> {
         let surface = wgpu::Surface::create(window);
-        let adapter = match wgpu::Adapter::request(
-            &wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::Default,
-                compatible_surface: Some(&surface),
-            },
-            wgpu::BackendBit::PRIMARY,
-        )
-        .await
-        {
-            Some(adapter) => adapter,
-            None => err!(""),
+        let (device, queue) = match Gpu::request_device(Some(&surface)).await {
+            Some(pair) => pair,
+            None => return Ok(Self::new_software0(physical_width, physical_height)),
         };
-        let (device, queue) = adapter
-            .request_device(&wgpu::DeviceDescriptor {
-                extensions: wgpu::Extensions {
-                    anisotropic_filtering: false,
-                },
-                limits: Default::default(),
-            })
-            .await;
-        let sc_desc = wgpu::SwapChainDescriptor {
-            usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT,
-            format: wgpu::TextureFormat::Bgra8UnormSrgb,
-            width: physical_width,
-            height: physical_height,
-            present_mode: wgpu::PresentMode::Fifo,
+        let screen = Screen::window(&device, surface, physical_width, physical_height);
+        let gpu = Gpu::new(device, queue, screen)?;
+        Ok(Self::from_renderer(Renderer::Gpu(Box::new(gpu))))
+    }
+
+    pub(super) async fn new_offscreen0(width: u32, height: u32) -> Result<Self> {
+        let (device, queue) = match Gpu::request_device(None).await {
+            Some(pair) => pair,
+            None => return Ok(Self::new_software0(width, height)),
         };
-        let swap_chain = device.create_swap_chain(&surface, &sc_desc);
-        // compile shaders
-        let vs_data = wgpu::read_spirv(std::io::Cursor::new(shaders::VERT))?;
-        let fs_data = wgpu::read_spirv(std::io::Cursor::new(shaders::FRAG))?;
-        let vs_module = device.create_shader_module(&vs_data);
-        let fs_module = device.create_shader_module(&fs_data);
-
-        // sheet bind layout
-        let texture_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                bindings: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStage::FRAGMENT,
-                        ty: wgpu::BindingType::SampledTexture {
-                            multisampled: false,
-                            dimension: wgpu::TextureViewDimension::D2,
-                            component_type: wgpu::TextureComponentType::Uint,
-                        },
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStage::FRAGMENT,
-                        ty: wgpu::BindingType::Sampler { comparison: false },
-                    },
-                ],
-                label: Some("texture_bind_group_layout"),
-            });
-
-        // scale uniform bind layout
-        let scale_uniform_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                bindings: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStage::VERTEX,
-                    ty: wgpu::BindingType::UniformBuffer { dynamic: false },
-                }],
-                label: Some("scale_uniform_bind_group_layout"),
-            });
-
-        // translation uniform bind layout
-        let translation_uniform_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                bindings: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStage::VERTEX,
-                    ty: wgpu::BindingType::UniformBuffer { dynamic: false },
-                }],
-                label: Some("translation_uniform_bind_group_layout"),
-            });
-
-        // build the pipeline
-        let render_pipeline_layout =
-            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                bind_group_layouts: &[
-                    &texture_bind_group_layout,
-                    &scale_uniform_bind_group_layout,
-                    &translation_uniform_bind_group_layout,
-                ],
-            });
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            layout: &render_pipeline_layout,
-            vertex_stage: wgpu::ProgrammableStageDescriptor {
-                module: &vs_module,
-                entry_point: "main",
-            },
-            fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
-                module: &fs_module,
-                entry_point: "main",
-            }),
-            rasterization_state: Some(wgpu::RasterizationStateDescriptor {
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: wgpu::CullMode::Back,
-                depth_bias: 0,
-                depth_bias_slope_scale: 0.0,
-                depth_bias_clamp: 0.0,
-            }),
-            color_states: &[wgpu::ColorStateDescriptor {
-                format: sc_desc.format,
-                color_blend: wgpu::BlendDescriptor {
-                    src_factor: wgpu::BlendFactor::SrcAlpha,
-                    dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
-                    operation: wgpu::BlendOperation::Add,
-                },
-                alpha_blend: wgpu::BlendDescriptor::REPLACE,
-                write_mask: wgpu::ColorWrite::ALL,
-            }],
-            primitive_topology: wgpu::PrimitiveTopology::TriangleList,
-            depth_stencil_state: None,
-            vertex_state: wgpu::VertexStateDescriptor {
-                index_format: wgpu::IndexFormat::Uint16,
-                vertex_buffers: &[Instance::desc()],
-            },
-            sample_count: 1,
-            sample_mask: !0,
-            alpha_to_coverage_enabled: false,
-        });
-
-        let scale = [1.0, 1.0];
-        let scale_uniform_buffer = device
-            .create_buffer_with_data(bytemuck::cast_slice(&scale), wgpu::BufferUsage::UNIFORM);
-
-        Ok(Self {
-            surface,
-            device: Arc::new(device),
-            queue,
-            sc_desc,
-            swap_chain,
-            scale_uniform_bind_group_layout,
-            translation_uniform_bind_group_layout,
-            render_pipeline,
-            texture_bind_group_layout,
-            scale,
-            scale_uniform_buffer,
+        let screen = Screen::offscreen(&device, width, height);
+        let gpu = Gpu::new(device, queue, screen)?;
+        Ok(Self::from_renderer(Renderer::Gpu(Box::new(gpu))))
+    }
+
+    pub(super) fn new_software0(width: u32, height: u32) -> Self {
+        Self::from_renderer(Renderer::Soft {
+            width,
+            height,
+            frame: vec![0; (width * height * 4) as usize],
+        })
+    }
+
+    fn from_renderer(renderer: Renderer) -> Self {
+        Self {
+            renderer,
+            scale: [1.0, 1.0],
+            camera: None,
             batches: Default::default(),
+            builtin_batches: Default::default(),
+            clear_color: Some([0.0, 0.0, 0.0, 0.0].into()),
+            background: None,
+            text_atlas: None,
+            free_text: None,
             text_grid_dim: None,
             dirty: true,
-            poll_thread: None,
-        })
+        }
+    }
+
+    /// The batches drawn in a frame, in drawing order, each paired with
+    /// whether it's viewed through the camera
+    fn frame_batches(&self) -> Vec<(&Batch, bool)> {
+        // The builtin batches are always drawn in screen coordinates
+        let background = self.background.iter().map(|batch| (batch, false));
+        let batches = self
+            .batches
+            .iter()
+            .rev()
+            .flatten()
+            .map(|batch| (batch, batch.follows_camera()));
+        let builtin_batches = self
+            .builtin_batches
+            .iter()
+            .rev()
+            .flatten()
+            .map(|batch| (batch, false));
+        background.chain(batches).chain(builtin_batches).collect()
+    }
+
+    /// Creates the pipelines for any blend mode and shader combinations
+    /// used by the current batches that haven't been used before,
+    /// and the resources needed by the post-processing effects
+    pub(super) fn prepare_frame(&mut self) -> Result<()> {
+        let gpu = match &mut self.renderer {
+            Renderer::Gpu(gpu) => gpu,
+            Renderer::Soft { .. } => return Ok(()),
+        };
+        let batches = self
+            .background
+            .iter()
+            .chain(self.batches.iter().flatten())
+            .chain(self.builtin_batches.iter().flatten());
+        let missing = gpu.missing_pipelines(gpu.scene_format(), batches);
+        gpu.pipelines.extend(missing);
+        gpu.post.prepare(
+            &gpu.device,
+            &gpu.texture_bind_group_layout,
+            gpu.screen.size(),
+            gpu.screen.format(),
+        )
+    }
+
+    /// Records the commands for drawing a whole frame into the given view,
+    /// going through the post-processing effects if there are any.
+    /// `prepare_frame` must have been called first
+    ///
+    /// `clear_color` is used instead of the Graphics2D's own clear color
+    /// when the view's previous contents must not be kept
+    pub(super) fn encode_frame(
+        &self,
+        gpu: &Gpu,
+        view: &wgpu::TextureView,
+        clear_color: Option<Color>,
+    ) -> Vec<wgpu::CommandBuffer> {
+        if gpu.post.is_active() {
+            // The post-processing passes overwrite the whole view, so only
+            // the scene texture (which is kept between frames) is loaded
+            vec![
+                self.encode_render(gpu, gpu.post.scene_view(), self.clear_color),
+                gpu.post.encode(&gpu.device, view, gpu.screen.format()),
+            ]
+        } else {
+            vec![self.encode_render(gpu, view, clear_color)]
+        }
+    }
+
+    /// Records the commands for drawing all batches into the given view
+    fn encode_render(
+        &self,
+        gpu: &Gpu,
+        view: &wgpu::TextureView,
+        clear_color: Option<Color>,
+    ) -> wgpu::CommandBuffer {
+        let target = PassTarget {
+            view,
+            depth_view: &gpu.depth_view,
+            format: gpu.scene_format(),
+            size: gpu.screen.size(),
+            scale: self.scale,
+            camera: self.camera(),
+            clear_color,
+        };
+        gpu.encode_batches(&target, &self.frame_batches())
+    }
+
+    /// Draws all batches with the software renderer, replacing the
+    /// frame that `screenshot` and `read_pixels` return
+    pub(super) fn render_soft(&mut self) {
+        let (width, height, frame) = match &self.renderer {
+            Renderer::Soft {
+                width,
+                height,
+                frame,
+            } => (*width, *height, frame),
+            Renderer::Gpu(_) => return,
+        };
+        let mut canvas = Canvas::new(width, height, self.clear_color, frame);
+        canvas.draw_batches(self.scale, self.camera(), &self.frame_batches());
+        if let Renderer::Soft { frame, .. } = &mut self.renderer {
+            *frame = canvas.into_rgba();
+        }
     }
 
     pub(super) fn pixel_batch(&mut self) -> Result<&mut Batch> {
-        if self.batches[BATCH_SLOT_PIXEL].is_none() {
+        if self.builtin_batches[BUILTIN_PIXEL].is_none() {
             let [width, height] = self.scale();
             let width = width as usize;
             let height = height as usize;
@@ -170,6 +166,12 @@ impl Graphics2D {
                 for x in 0..width {
                     let x = x as f32;
                     descs.push(SpriteDesc {
+                        src_rect: None,
+                        flip: [false, false],
+                        z: BUILTIN_Z,
+                        visible: true,
+                        transform: Affine::IDENTITY,
+                        tiling: None,
                         src: 0,
                         dst: [x, y, x + 1.0, y + 1.0].into(),
                         rotate: 0.0,
@@ -179,45 +181,107 @@ impl Graphics2D {
             }
             let sheet = Sheet::from_color(self, [1.0, 1.0, 1.0])?;
             let batch = Batch::new(self, sheet, 1, 1, &descs);
-            self.batches[BATCH_SLOT_PIXEL] = Some(batch);
+            self.builtin_batches[BUILTIN_PIXEL] = Some(batch);
         }
-        Ok(self.batches[BATCH_SLOT_PIXEL].as_mut().unwrap())
+        Ok(self.builtin_batches[BUILTIN_PIXEL].as_mut().unwrap())
     }
 
     pub(super) fn text_batch(&mut self) -> Result<&mut Batch> {
-        Ok(self.batches[BATCH_SLOT_TEXT].as_mut().unwrap())
+        Ok(self.builtin_batches[BUILTIN_TEXT].as_mut().unwrap())
     }
 
-    pub(super) fn ensure_polling(&mut self) -> Result<()> {
-        if self.poll_thread.is_none() {
-            let device = self.device.clone();
-            let (sender, receiver) = std::sync::mpsc::channel();
-            let thread = std::thread::Builder::new()
-                .name("a2d-wgpu-poll".to_owned())
-                .spawn(move || loop {
-                    match receiver.try_recv() {
-                        Ok(()) | Err(std::sync::mpsc::TryRecvError::Disconnected) => break,
-                        Err(std::sync::mpsc::TryRecvError::Empty) => {}
-                    }
-                    device.poll(wgpu::Maintain::Wait);
-                    std::thread::sleep(POLL_SLEEP_DUR);
-                    std::thread::yield_now();
-                })?;
-            self.poll_thread = Some((thread, sender));
-        }
+    pub(super) fn text_background_batch(&mut self) -> Result<&mut Batch> {
+        Ok(self.builtin_batches[BUILTIN_TEXT_BACKGROUND]
+            .as_mut()
+            .unwrap())
+    }
+
+    pub(super) fn init_text_background(&mut self, descs: &[SpriteDesc]) -> Result<()> {
+        let sheet = Sheet::from_color(self, [1.0, 1.0, 1.0])?;
+        self.builtin_batches[BUILTIN_TEXT_BACKGROUND] = Some(Batch::new(self, sheet, 1, 1, descs));
         Ok(())
     }
 
-    pub(super) async fn async_flush(&mut self) -> Result<()> {
-        let futs: Vec<_> = self
-            .batches
-            .iter_mut()
-            .flatten()
-            .map(Batch::flush)
-            .collect();
-        let futs = futures::future::try_join_all(futs);
-        self.device.poll(wgpu::Maintain::Wait);
-        futs.await?;
+    pub(super) fn free_text(&mut self) -> Result<&mut FreeText> {
+        if self.free_text.is_none() {
+            let sheet = Sheet::from_bytes(self, res::COURIER_CHARMAP)?;
+            self.free_text = Some(FreeText::new(TextFace::Courier, sheet));
+        }
+        Ok(self.free_text.as_mut().unwrap())
+    }
+
+    /// Updates the free text batch with any text drawn or cleared
+    /// since the last flush
+    pub(super) fn rebuild_free_text(&mut self) {
+        if let Some(mut free_text) = self.free_text.take() {
+            let mut batch = self.builtin_batches[BUILTIN_FREE_TEXT].take();
+            free_text.rebuild(self, &mut batch);
+            self.builtin_batches[BUILTIN_FREE_TEXT] = batch;
+            self.free_text = Some(free_text);
+        }
+    }
+
+    /// Returns the source index in the text batch's sheet to use
+    /// for drawing the given character
+    pub(super) fn text_src_index(&mut self, ch: char) -> usize {
+        match &mut self.text_atlas {
+            Some(atlas) => atlas.index(self.renderer.gpu(), ch),
+            None => res::char_to_charmap_index(ch).unwrap_or(res::CHAR_FALLBACK_INDEX),
+        }
+    }
+
+    /// Returns the index of the sprite for the given cell of the text grid,
+    /// initializing the grid if needed.
+    /// Returns None if the cell is out of bounds
+    pub(super) fn text_cell_index(&mut self, row: usize, col: usize) -> Result<Option<usize>> {
+        if self.text_grid_dim.is_none() {
+            self.init_text_grid(DEFAULT_TEXT_NCOLS)?;
+        }
+        let TextGridDim { nrows, ncols } = self.text_grid_dim.unwrap();
+        if row < nrows && col < ncols {
+            Ok(Some(ncols * row + col))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub(super) fn check_user_slot(&self, slot: usize) -> Result<()> {
+        if slot >= SLOT_LIMIT {
+            err!(
+                "Batch slot {} is out of bounds (SLOT_LIMIT = {})",
+                slot,
+                SLOT_LIMIT,
+            );
+        }
         Ok(())
     }
+
+    /// Needed before reading back from the GPU.
+    /// Does nothing with the software renderer
+    pub(super) fn ensure_polling(&mut self) -> Result<()> {
+        match self.renderer.gpu_mut() {
+            Some(gpu) => gpu.ensure_polling(),
+            None => Ok(()),
+        }
+    }
+
+    pub(super) fn flush_batches(&mut self) {
+        match &self.renderer {
+            Renderer::Gpu(gpu) => gpu.flush_batches(
+                self.batches
+                    .iter_mut()
+                    .chain(self.builtin_batches.iter_mut())
+                    .flatten(),
+            ),
+            Renderer::Soft { .. } => {
+                let batches = self
+                    .batches
+                    .iter_mut()
+                    .chain(self.builtin_batches.iter_mut());
+                for batch in batches.flatten() {
+                    batch.flush_soft();
+                }
+            }
+        }
+    }
 }
@@ -34,6 +34,26 @@ pub(super) struct Instance {
     ///
     /// Defaults to [1.0, 1.0, 1.0, 1.0], so that the color remains unchanged
     color_factor: [f32; 4],
+
+    /// Depth between -1.0 and 1.0, higher is in front
+    z: f32,
+
+    /// 1.0 if the sprite should be drawn, 0.0 otherwise.
+    /// Zeroed instances are hidden
+    visible: f32,
+
+    /// Rows of the affine transformation applied after `rotate`,
+    /// relative to the upper-left corner of the destination rectangle.
+    /// [a, c, e] and [b, d, f] in terms of `Affine`
+    transform_x: [f32; 3],
+    transform_y: [f32; 3],
+
+    /// How many times the source is repeated across the destination,
+    /// or [0.0, 0.0] if it is stretched over it instead
+    tile_repeat: [f32; 2],
+
+    /// Scroll offset of the tiles, in units of one tile
+    tile_offset: [f32; 2],
 }
 
 unsafe impl bytemuck::Pod for Instance {}
@@ -49,6 +69,11 @@ impl Instance {
             dest: [0.0, 0.0, 1.0, 1.0].into(),
             rotate: 0.0,
             color_factor: [1.0, 1.0, 1.0, 1.0],
+            flip: [false, false],
+            z: 0.0,
+            visible: true,
+            transform: Affine::IDENTITY,
+            tiling: None,
         }
     }
     fn new<R1: Into<Rect>, R2: Into<Rect>>(
@@ -66,17 +91,59 @@ impl Instance {
             dst_lr: dest.lower_right(),
             rotate,
             color_factor,
+            z: 0.0,
+            visible: 1.0,
+            transform_x: [1.0, 0.0, 0.0],
+            transform_y: [0.0, 1.0, 0.0],
+            tile_repeat: [0.0, 0.0],
+            tile_offset: [0.0, 0.0],
         }
     }
 
+    /// The corners of the source rectangle, swapped if flipped.
+    /// Unlike `src`, this works for zeroed instances
+    pub fn src_corners(&self) -> [[f32; 2]; 2] {
+        [self.src_ul, self.src_lr]
+    }
+
     pub fn src(&self) -> Rect {
         [self.src_ul, self.src_lr].into()
     }
 
+    /// Sets the source rectangle, keeping the current flip
     pub fn set_src<R: Into<Rect>>(&mut self, rect: R) {
+        let [flip_x, flip_y] = self.flip();
         let rect = rect.into();
         self.src_ul = rect.upper_left();
         self.src_lr = rect.lower_right();
+        self.set_flip([flip_x, flip_y]);
+    }
+
+    /// Whether the source is mirrored [horizontally, vertically]
+    ///
+    /// Flipping is represented by swapping the source corners, so that
+    /// the shader maps the source onto the destination backwards
+    pub fn flip(&self) -> [bool; 2] {
+        [
+            self.src_ul[0] > self.src_lr[0],
+            self.src_ul[1] > self.src_lr[1],
+        ]
+    }
+
+    pub fn set_flip(&mut self, flip: [bool; 2]) {
+        let src = self.src();
+        let [x1, y1] = src.upper_left();
+        let [x2, y2] = src.lower_right();
+        let (x1, x2) = if flip[0] { (x2, x1) } else { (x1, x2) };
+        let (y1, y2) = if flip[1] { (y2, y1) } else { (y1, y2) };
+        self.src_ul = [x1, y1];
+        self.src_lr = [x2, y2];
+    }
+
+    /// The corners of the destination rectangle.
+    /// Unlike `dest`, this works for zeroed instances
+    pub fn dst_corners(&self) -> [[f32; 2]; 2] {
+        [self.dst_ul, self.dst_lr]
     }
 
     pub fn dest(&self) -> Rect {
@@ -97,10 +164,71 @@ impl Instance {
         self.rotate = rotate;
     }
 
+    pub fn color_factor(&self) -> [f32; 4] {
+        self.color_factor
+    }
+
     pub fn set_color_factor<C: Into<Color>>(&mut self, color_factor: C) {
         self.color_factor = color_factor.into().to_array();
     }
 
+    pub fn z(&self) -> f32 {
+        self.z
+    }
+
+    /// Clamped to the depth range, so that the sprite isn't clipped
+    pub fn set_z(&mut self, z: f32) {
+        self.z = z.clamp(-1.0, 1.0);
+    }
+
+    pub fn visible(&self) -> bool {
+        self.visible != 0.0
+    }
+
+    pub fn set_visible(&mut self, visible: bool) {
+        self.visible = if visible { 1.0 } else { 0.0 };
+    }
+
+    pub fn transform(&self) -> Affine {
+        let [a, c, e] = self.transform_x;
+        let [b, d, f] = self.transform_y;
+        Affine::new(a, b, c, d, e, f)
+    }
+
+    pub fn set_transform(&mut self, transform: Affine) {
+        let [a, b, c, d, e, f] = transform.to_array();
+        self.transform_x = [a, c, e];
+        self.transform_y = [b, d, f];
+    }
+
+    pub fn tiling(&self) -> Option<Tiling> {
+        if self.tile_repeat == [0.0, 0.0] {
+            None
+        } else {
+            Some(Tiling {
+                repeat: self.tile_repeat,
+                offset: self.tile_offset,
+            })
+        }
+    }
+
+    pub fn set_tiling(&mut self, tiling: Option<Tiling>) {
+        match tiling {
+            Some(tiling) => {
+                self.tile_repeat = tiling.repeat;
+                self.tile_offset = tiling.offset;
+            }
+            None => {
+                self.tile_repeat = [0.0, 0.0];
+                self.tile_offset = [0.0, 0.0];
+            }
+        }
+    }
+
+    pub fn set_tile_offset(&mut self, offset: [f32; 2]) {
+        self.tile_offset = offset;
+    }
+
     pub(super) fn desc<'a>() -> wgpu::VertexBufferDescriptor<'a> {
         assert_eq!(
             std::mem::align_of::<Instance>(),
@@ -108,7 +236,7 @@ impl Instance {
         );
         assert_eq!(
             std::mem::size_of::<Instance>(),
-            std::mem::size_of::<f32>() * 13,
+            std::mem::size_of::<f32>() * 25,
         );
         use std::mem;
         wgpu::VertexBufferDescriptor {
@@ -145,6 +273,36 @@ impl Instance {
                     shader_location: 5,
                     format: wgpu::VertexFormat::Float4,
                 },
+                wgpu::VertexAttributeDescriptor {
+                    offset: FLOAT_SIZE * (2 + 2 + 2 + 2 + 1 + 4),
+                    shader_location: 6,
+                    format: wgpu::VertexFormat::Float,
+                },
+                wgpu::VertexAttributeDescriptor {
+                    offset: FLOAT_SIZE * (2 + 2 + 2 + 2 + 1 + 4 + 1),
+                    shader_location: 7,
+                    format: wgpu::VertexFormat::Float,
+                },
+                wgpu::VertexAttributeDescriptor {
+                    offset: FLOAT_SIZE * (2 + 2 + 2 + 2 + 1 + 4 + 1 + 1),
+                    shader_location: 8,
+                    format: wgpu::VertexFormat::Float3,
+                },
+                wgpu::VertexAttributeDescriptor {
+                    offset: FLOAT_SIZE * (2 + 2 + 2 + 2 + 1 + 4 + 1 + 1 + 3),
+                    shader_location: 9,
+                    format: wgpu::VertexFormat::Float3,
+                },
+                wgpu::VertexAttributeDescriptor {
+                    offset: FLOAT_SIZE * (2 + 2 + 2 + 2 + 1 + 4 + 1 + 1 + 3 + 3),
+                    shader_location: 10,
+                    format: wgpu::VertexFormat::Float2,
+                },
+                wgpu::VertexAttributeDescriptor {
+                    offset: FLOAT_SIZE * (2 + 2 + 2 + 2 + 1 + 4 + 1 + 1 + 3 + 3 + 2),
+                    shader_location: 11,
+                    format: wgpu::VertexFormat::Float2,
+                },
             ],
         }
     }
@@ -155,11 +313,47 @@ pub(super) struct InstanceBuilder {
     dest: Rect,
     rotate: f32,
     color_factor: [f32; 4],
+    flip: [bool; 2],
+    z: f32,
+    visible: bool,
+    transform: Affine,
+    tiling: Option<Tiling>,
 }
 
 impl InstanceBuilder {
     pub fn build(self) -> Instance {
-        Instance::new(self.src, self.dest, self.rotate, self.color_factor)
+        let mut instance = Instance::new(self.src, self.dest, self.rotate, self.color_factor);
+        instance.set_flip(self.flip);
+        instance.set_z(self.z);
+        instance.set_visible(self.visible);
+        instance.set_transform(self.transform);
+        instance.set_tiling(self.tiling);
+        instance
+    }
+
+    pub fn tiling(mut self, tiling: Option<Tiling>) -> Self {
+        self.tiling = tiling;
+        self
+    }
+
+    pub fn transform(mut self, transform: Affine) -> Self {
+        self.transform = transform;
+        self
+    }
+
+    pub fn z(mut self, z: f32) -> Self {
+        self.z = z;
+        self
+    }
+
+    pub fn visible(mut self, visible: bool) -> Self {
+        self.visible = visible;
+        self
+    }
+
+    pub fn flip(mut self, flip: [bool; 2]) -> Self {
+        self.flip = flip;
+        self
     }
 
     pub fn src<R: Into<Rect>>(mut self, src: R) -> Self {
@@ -1,58 +1,147 @@
 use crate::res;
 use crate::shaders;
+use crate::Affine;
 use crate::Color;
+use crate::Dimensions;
+use crate::Point;
 use crate::Rect;
 use crate::Result;
 use crate::Scaling;
 use crate::Translation;
 use raw_window_handle::HasRawWindowHandle;
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::rc::Rc;
 use std::sync::Arc;
 use std::time::Duration;
 
+mod atlas;
 mod batch;
+mod blend;
+mod camera;
+mod font;
+mod gpu;
 mod iface;
 mod imp;
 mod inst;
+mod packer;
+mod post;
+mod raster;
+mod screen;
+mod screenshot;
+mod shader;
 mod sheet;
+mod soft;
 mod sprite;
+mod target;
+mod text;
 
-use batch::*;
+use atlas::*;
+use gpu::*;
 use inst::*;
-use sheet::*;
-use sprite::*;
+use raster::*;
+use screen::*;
+use soft::*;
 
+pub use batch::*;
+pub use blend::*;
+pub use camera::*;
+pub use font::*;
 pub use iface::*;
+pub use packer::*;
+pub use post::*;
+pub use screenshot::*;
+pub use shader::*;
+pub use sheet::*;
+pub use sprite::*;
+pub use target::*;
+pub use text::*;
 
+/// Number of slots available for user batches
 pub const SLOT_LIMIT: usize = 16;
 
-pub const BATCH_SLOT_TEXT: usize = 0;
-pub const BATCH_SLOT_PIXEL: usize = 1;
+/// The builtin batches are kept apart from the user slots and drawn
+/// on top of them. Like slots, lower indices are drawn on top
+const BUILTIN_TEXT: usize = 0;
+const BUILTIN_TEXT_BACKGROUND: usize = 1;
+const BUILTIN_FREE_TEXT: usize = 2;
+const BUILTIN_PIXEL: usize = 3;
+const BUILTIN_LIMIT: usize = 4;
+
+/// The z of every builtin sprite, so that user sprites can't be
+/// drawn in front of them however their z is set
+const BUILTIN_Z: f32 = 1.0;
 
 pub const DEFAULT_TEXT_NCOLS: usize = 80;
 
+/// Pixel height glyphs are rasterized at for `init_text_grid_with_font`
+pub const TEXT_GRID_FONT_PX_HEIGHT: u32 = 32;
+
+/// Pixel height glyphs are rasterized at for `draw_text_at`.
+/// Text drawn larger than this is scaled up from these glyphs
+pub const FREE_TEXT_FONT_PX_HEIGHT: u32 = 48;
+
 pub struct Graphics2D {
-    surface: wgpu::Surface,
-    device: Arc<wgpu::Device>,
-    queue: wgpu::Queue,
-    sc_desc: wgpu::SwapChainDescriptor,
-    swap_chain: wgpu::SwapChain,
-    scale_uniform_bind_group_layout: wgpu::BindGroupLayout,
-    translation_uniform_bind_group_layout: wgpu::BindGroupLayout,
-    render_pipeline: wgpu::RenderPipeline,
-    texture_bind_group_layout: wgpu::BindGroupLayout,
+    renderer: Renderer,
     scale: Scaling,
-    scale_uniform_buffer: wgpu::Buffer,
+
+    /// View applied to user batches, if any.
+    /// None is the same as `Camera::identity`
+    camera: Option<Camera>,
 
     batches: [Option<Batch>; SLOT_LIMIT],
 
+    /// The text grid, free text and pixel batches
+    builtin_batches: [Option<Batch>; BUILTIN_LIMIT],
+
+    /// Color the frame is cleared to before drawing.
+    /// If None, the previous contents of the frame are kept
+    clear_color: Option<Color>,
+
+    /// Single sprite batch stretched over the whole drawing area,
+    /// drawn underneath all other batches
+    background: Option<Batch>,
+
     text_grid_dim: Option<TextGridDim>,
 
+    /// Glyphs for the text grid if it was initialized with a Font
+    /// instead of the builtin courier charmap
+    text_atlas: Option<GlyphAtlas>,
+
+    /// Text drawn with `draw_text_at`, lazily created
+    free_text: Option<FreeText>,
+
     /// Used by render_if_dirty to determine if there's been
     /// any change since the last render
     dirty: bool,
+}
+
+/// What a Graphics2D draws with
+enum Renderer {
+    /// Boxed since it's much larger than the other variant
+    Gpu(Box<Gpu>),
+
+    /// Draws on the CPU when no wgpu adapter is available.
+    /// `frame` holds the RGBA bytes of the last rendered frame
+    Soft {
+        width: u32,
+        height: u32,
+        frame: Vec<u8>,
+    },
+}
+
+impl Renderer {
+    fn gpu(&self) -> Option<&Gpu> {
+        match self {
+            Renderer::Gpu(gpu) => Some(gpu),
+            Renderer::Soft { .. } => None,
+        }
+    }
 
-    /// For reading and writing from wgpu buffers, device.poll(..) needs
-    /// to be called continuously.
-    poll_thread: Option<(std::thread::JoinHandle<()>, std::sync::mpsc::Sender<()>)>,
+    fn gpu_mut(&mut self) -> Option<&mut Gpu> {
+        match self {
+            Renderer::Gpu(gpu) => Some(gpu),
+            Renderer::Soft { .. } => None,
+        }
+    }
 }
@@ -0,0 +1,454 @@
+use super::*;
+use std::collections::HashMap;
+
+/// Default maximum width and height of each sheet built by AtlasBuilder
+pub const DEFAULT_ATLAS_MAX_SIZE: u32 = 2048;
+
+/// Packs many differently sized images into as few sheets as possible
+///
+/// Images are packed into rows ("shelves"), tallest first, and
+/// a new sheet is started whenever the current one is full.
+pub struct AtlasBuilder {
+    max_size: u32,
+    padding: u32,
+    trim: bool,
+    images: Vec<(String, image::RgbaImage)>,
+}
+
+/// Where an image added to an AtlasBuilder ended up
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AtlasRegion {
+    /// Index into `Atlas::sheets`
+    pub sheet: usize,
+
+    /// The region of the sheet in normalized coordinates
+    /// (between 0 and 1, as used by the shaders)
+    pub src: Rect,
+
+    /// The region of the sheet in pixels
+    pub pixels: Rect,
+
+    /// Size in pixels of the image before it was trimmed
+    pub original_size: [u32; 2],
+
+    /// Position in pixels of the upper-left corner of the trimmed
+    /// image within the original image. [0, 0] if the image wasn't trimmed
+    pub trim_offset: [u32; 2],
+}
+
+/// The sheets and named regions built by an AtlasBuilder
+pub struct Atlas {
+    sheets: Vec<Rc<Sheet>>,
+    regions: HashMap<String, AtlasRegion>,
+}
+
+impl Atlas {
+    pub fn sheets(&self) -> &[Rc<Sheet>] {
+        &self.sheets
+    }
+
+    /// Returns the region for the image added with the given name
+    pub fn get(&self, name: &str) -> Option<&AtlasRegion> {
+        self.regions.get(name)
+    }
+
+    pub fn regions(&self) -> &HashMap<String, AtlasRegion> {
+        &self.regions
+    }
+}
+
+impl Default for AtlasBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AtlasBuilder {
+    pub fn new() -> Self {
+        Self {
+            max_size: DEFAULT_ATLAS_MAX_SIZE,
+            padding: 1,
+            trim: false,
+            images: vec![],
+        }
+    }
+
+    /// Sets the maximum width and height of each sheet
+    pub fn max_size(mut self, max_size: u32) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Sets the number of transparent pixels left between images,
+    /// so that filtering doesn't bleed neighboring images into each other.
+    /// Defaults to 1
+    pub fn padding(mut self, padding: u32) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    /// If set, fully transparent rows and columns around the
+    /// edges of each image are removed before packing
+    pub fn trim(mut self, trim: bool) -> Self {
+        self.trim = trim;
+        self
+    }
+
+    /// Adds an image from image bytes
+    ///
+    /// The bytes are interpreted by passing the bytes to the
+    /// `load_from_memory` function from the `image` crate.
+    /// Fails if an image with the same name was already added
+    pub fn add_bytes<S: Into<String>>(&mut self, name: S, bytes: &[u8]) -> Result<()> {
+        let image = image::load_from_memory(bytes)?.to_rgba8();
+        self.add_image(name.into(), image)
+    }
+
+    /// Adds an image from raw RGBA bytes, 4 bytes per pixel.
+    /// Fails if an image with the same name was already added
+    pub fn add_rgba_bytes<S: Into<String>>(
+        &mut self,
+        name: S,
+        width: u32,
+        height: u32,
+        bytes: Vec<u8>,
+    ) -> Result<()> {
+        let image = match image::RgbaImage::from_raw(width, height, bytes) {
+            Some(img) => img,
+            None => {
+                err!("Failed to create image from rgba bytes for AtlasBuilder");
+            }
+        };
+        self.add_image(name.into(), image)
+    }
+
+    fn add_image(&mut self, name: String, image: image::RgbaImage) -> Result<()> {
+        if self.images.iter().any(|(other, _)| *other == name) {
+            err!("An image named {:?} was already added to the atlas", name);
+        }
+        self.images.push((name, image));
+        Ok(())
+    }
+
+    /// Packs all added images and uploads the resulting sheets
+    pub fn build(self, graphics: &mut Graphics2D) -> Result<Atlas> {
+        let padding = self.padding;
+        let max_size = self.max_size;
+        let trim = self.trim;
+
+        struct Entry {
+            name: String,
+            image: image::RgbaImage,
+            original_size: [u32; 2],
+            trim_offset: [u32; 2],
+        }
+        let mut packer = ShelfPacker::new(max_size, padding);
+        let mut entries = vec![];
+        for (name, image) in self.images {
+            let original_size = [image.width(), image.height()];
+            let (image, trim_offset) = if trim {
+                trim_transparent(image)
+            } else {
+                (image, [0, 0])
+            };
+            if !packer.fits(image.width(), image.height()) {
+                err!(
+                    "Image {:?} ({}x{}) does not fit in a {}x{} atlas sheet",
+                    name,
+                    image.width(),
+                    image.height(),
+                    max_size,
+                    max_size,
+                );
+            }
+            entries.push(Entry {
+                name,
+                image,
+                original_size,
+                trim_offset,
+            });
+        }
+
+        // Tallest first keeps the wasted space in each shelf small
+        entries.sort_by(|a, b| {
+            (b.image.height(), b.image.width()).cmp(&(a.image.height(), a.image.width()))
+        });
+
+        let packed: Vec<_> = entries
+            .iter()
+            .map(|entry| packer.place(entry.image.width(), entry.image.height()))
+            .collect();
+        let sheet_sizes = packer.into_sheet_sizes();
+
+        let mut canvases: Vec<_> = sheet_sizes
+            .iter()
+            .map(|&[w, h]| image::RgbaImage::new(w.max(1), h.max(1)))
+            .collect();
+        let mut regions = HashMap::new();
+        for (entry, p) in entries.iter().zip(&packed) {
+            let canvas = &mut canvases[p.sheet];
+            let (width, height) = entry.image.dimensions();
+            image::imageops::replace(canvas, &entry.image, p.x, p.y);
+            let (sheet_width, sheet_height) = (canvas.width() as f32, canvas.height() as f32);
+            let (x1, y1) = (p.x as f32, p.y as f32);
+            let (x2, y2) = (x1 + width.max(1) as f32, y1 + height.max(1) as f32);
+            regions.insert(
+                entry.name.clone(),
+                AtlasRegion {
+                    sheet: p.sheet,
+                    src: [
+                        x1 / sheet_width,
+                        y1 / sheet_height,
+                        x2 / sheet_width,
+                        y2 / sheet_height,
+                    ]
+                    .into(),
+                    pixels: [x1, y1, x2, y2].into(),
+                    original_size: entry.original_size,
+                    trim_offset: entry.trim_offset,
+                },
+            );
+        }
+
+        let mut sheets = vec![];
+        for canvas in canvases {
+            let (width, height) = canvas.dimensions();
+            sheets.push(Sheet::from_rgba_bytes(
+                graphics,
+                width,
+                height,
+                canvas.into_raw(),
+            )?);
+        }
+        Ok(Atlas { sheets, regions })
+    }
+}
+
+/// Where `ShelfPacker::place` put an image
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Placement {
+    sheet: usize,
+    x: u32,
+    y: u32,
+}
+
+/// Places images into rows ("shelves") of as few sheets as possible,
+/// starting a new sheet whenever the current one is full.
+///
+/// Every image is surrounded by at least `padding` pixels that no
+/// other image uses, including along the edges of the sheet
+struct ShelfPacker {
+    max_size: u32,
+    padding: u32,
+    sheet_sizes: Vec<[u32; 2]>,
+    shelf_x: u32,
+    shelf_y: u32,
+    shelf_height: u32,
+}
+
+impl ShelfPacker {
+    fn new(max_size: u32, padding: u32) -> Self {
+        Self {
+            max_size,
+            padding,
+            sheet_sizes: vec![],
+            shelf_x: padding,
+            shelf_y: padding,
+            shelf_height: 0,
+        }
+    }
+
+    /// Whether an image of the given size fits in a sheet at all
+    fn fits(&self, width: u32, height: u32) -> bool {
+        let padded = |size: u32| size as u64 + 2 * self.padding as u64;
+        let max_size = self.max_size as u64;
+        padded(width) <= max_size && padded(height) <= max_size
+    }
+
+    /// Places an image, which must `fit`, after the previously placed ones
+    fn place(&mut self, width: u32, height: u32) -> Placement {
+        debug_assert!(self.fits(width, height));
+        let padding = self.padding;
+        if self.shelf_x + width + padding > self.max_size {
+            // start a new shelf
+            self.shelf_y += self.shelf_height + padding;
+            self.shelf_x = padding;
+            self.shelf_height = 0;
+        }
+        if self.sheet_sizes.is_empty() || self.shelf_y + height + padding > self.max_size {
+            // start a new sheet
+            self.sheet_sizes.push([0, 0]);
+            self.shelf_x = padding;
+            self.shelf_y = padding;
+            self.shelf_height = 0;
+        }
+        let sheet = self.sheet_sizes.len() - 1;
+        let placement = Placement {
+            sheet,
+            x: self.shelf_x,
+            y: self.shelf_y,
+        };
+        let size = &mut self.sheet_sizes[sheet];
+        size[0] = size[0].max(self.shelf_x + width + padding);
+        size[1] = size[1].max(self.shelf_y + height + padding);
+        self.shelf_x += width + padding;
+        self.shelf_height = self.shelf_height.max(height);
+        placement
+    }
+
+    /// The size of every sheet that images were placed on
+    fn into_sheet_sizes(self) -> Vec<[u32; 2]> {
+        self.sheet_sizes
+    }
+}
+
+/// Removes fully transparent rows and columns from the edges of an image.
+/// Returns the trimmed image and the position it was cropped from.
+/// Images that are entirely transparent are trimmed to a single pixel.
+fn trim_transparent(image: image::RgbaImage) -> (image::RgbaImage, [u32; 2]) {
+    let (width, height) = image.dimensions();
+    let opaque = |x: u32, y: u32| image.get_pixel(x, y)[3] != 0;
+    let col_used = |x: u32| (0..height).any(|y| opaque(x, y));
+    let row_used = |y: u32| (0..width).any(|x| opaque(x, y));
+    let left = match (0..width).find(|&x| col_used(x)) {
+        Some(left) => left,
+        None => {
+            let pixel = image::imageops::crop_imm(&image, 0, 0, 1, 1).to_image();
+            return (pixel, [0, 0]);
+        }
+    };
+    let right = (0..width).rev().find(|&x| col_used(x)).unwrap();
+    let top = (0..height).find(|&y| row_used(y)).unwrap();
+    let bottom = (0..height).rev().find(|&y| row_used(y)).unwrap();
+    let trimmed =
+        image::imageops::crop_imm(&image, left, top, right - left + 1, bottom - top + 1).to_image();
+    (trimmed, [left, top])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pack(max_size: u32, padding: u32, sizes: &[[u32; 2]]) -> (Vec<Placement>, Vec<[u32; 2]>) {
+        let mut packer = ShelfPacker::new(max_size, padding);
+        let placements = sizes
+            .iter()
+            .map(|&[width, height]| {
+                assert!(packer.fits(width, height));
+                packer.place(width, height)
+            })
+            .collect();
+        (placements, packer.into_sheet_sizes())
+    }
+
+    #[test]
+    fn fits_leaves_room_for_padding_on_both_sides() {
+        let packer = ShelfPacker::new(16, 2);
+        assert!(packer.fits(12, 12));
+        assert!(!packer.fits(13, 12));
+        assert!(!packer.fits(12, 14));
+        assert!(!ShelfPacker::new(16, u32::MAX).fits(0, 0));
+    }
+
+    #[test]
+    fn largest_image_fills_the_sheet() {
+        let (placements, sheet_sizes) = pack(16, 2, &[[12, 12]]);
+        assert_eq!(
+            placements,
+            vec![Placement {
+                sheet: 0,
+                x: 2,
+                y: 2
+            }]
+        );
+        assert_eq!(sheet_sizes, vec![[16, 16]]);
+    }
+
+    #[test]
+    fn shelves_and_sheets() {
+        let (placements, sheet_sizes) = pack(11, 1, &[[4, 4], [4, 3], [4, 4], [4, 4], [8, 2]]);
+        assert_eq!(
+            placements,
+            vec![
+                Placement {
+                    sheet: 0,
+                    x: 1,
+                    y: 1
+                },
+                Placement {
+                    sheet: 0,
+                    x: 6,
+                    y: 1
+                },
+                // The third image starts a new shelf below the tallest one
+                Placement {
+                    sheet: 0,
+                    x: 1,
+                    y: 6
+                },
+                Placement {
+                    sheet: 0,
+                    x: 6,
+                    y: 6
+                },
+                // and the last one a new sheet
+                Placement {
+                    sheet: 1,
+                    x: 1,
+                    y: 1
+                },
+            ]
+        );
+        assert_eq!(sheet_sizes, vec![[11, 11], [10, 4]]);
+    }
+
+    #[test]
+    fn no_empty_sheets() {
+        let (_, sheet_sizes) = pack(8, 0, &[]);
+        assert!(sheet_sizes.is_empty());
+        let (placements, sheet_sizes) = pack(8, 0, &[[8, 8], [8, 8]]);
+        assert_eq!(placements[1].sheet, 1);
+        assert_eq!(sheet_sizes, vec![[8, 8], [8, 8]]);
+    }
+
+    #[test]
+    fn duplicate_names_are_rejected() {
+        let mut builder = AtlasBuilder::new();
+        builder.add_rgba_bytes("a", 1, 1, vec![0; 4]).unwrap();
+        builder.add_rgba_bytes("b", 1, 1, vec![0; 4]).unwrap();
+        assert!(builder.add_rgba_bytes("a", 1, 1, vec![0; 4]).is_err());
+    }
+
+    #[test]
+    fn build() {
+        let mut graphics = Graphics2D::new_software(1, 1);
+        let mut builder = AtlasBuilder::new().max_size(8).padding(1);
+        builder
+            .add_rgba_bytes("big", 6, 6, vec![255; 6 * 6 * 4])
+            .unwrap();
+        builder
+            .add_rgba_bytes("small", 2, 2, vec![255; 2 * 2 * 4])
+            .unwrap();
+        builder
+            .add_rgba_bytes("too big", 7, 1, vec![255; 7 * 4])
+            .unwrap();
+        assert!(builder.build(&mut graphics).is_err());
+
+        let mut builder = AtlasBuilder::new().max_size(8).padding(1);
+        builder
+            .add_rgba_bytes("big", 6, 6, vec![255; 6 * 6 * 4])
+            .unwrap();
+        builder
+            .add_rgba_bytes("small", 2, 2, vec![255; 2 * 2 * 4])
+            .unwrap();
+        let atlas = builder.build(&mut graphics).unwrap();
+        assert_eq!(atlas.sheets().len(), 2);
+        let big = atlas.get("big").unwrap();
+        assert_eq!(big.sheet, 0);
+        assert_eq!(big.pixels, [1.0, 1.0, 7.0, 7.0].into());
+        assert_eq!(big.src, [0.125, 0.125, 0.875, 0.875].into());
+        let small = atlas.get("small").unwrap();
+        assert_eq!(small.sheet, 1);
+        assert_eq!(atlas.sheets()[1].width(), 4);
+    }
+}
@@ -0,0 +1,689 @@
+use super::*;
+
+/// A full-screen effect applied to the whole frame after all
+/// batches are drawn (see `Graphics2D::set_post_effects`)
+#[derive(Clone)]
+pub enum PostEffect {
+    /// Gaussian blur reaching `radius` pixels in every direction
+    Blur { radius: f32 },
+
+    /// Makes the parts of the image brighter than `threshold`
+    /// (a luminance between 0 and 1) glow over their surroundings
+    Bloom {
+        threshold: f32,
+        intensity: f32,
+        radius: f32,
+    },
+
+    /// Darkens the image towards its edges.
+    ///
+    /// `radius` is the distance from the center where darkening starts,
+    /// with 1.0 being the middle of each edge, and `softness` is how far
+    /// it takes to reach full `strength`
+    Vignette {
+        strength: f32,
+        radius: f32,
+        softness: f32,
+    },
+
+    /// Remaps colors through a lookup table made with
+    /// `Sheet::color_lut` (or any sheet with the same layout).
+    ///
+    /// `strength` blends between the original (0.0) and remapped (1.0) colors
+    ColorGrade { lut: Rc<Sheet>, strength: f32 },
+
+    /// An old CRT monitor look: curved glass, horizontal scanlines every
+    /// `scanline_period` pixels and red, green and blue grille stripes
+    Crt {
+        scanline_intensity: f32,
+        curvature: f32,
+        grille_intensity: f32,
+        scanline_period: f32,
+    },
+}
+
+impl PostEffect {
+    pub fn blur(radius: f32) -> Self {
+        PostEffect::Blur { radius }
+    }
+
+    /// Bloom with settings that suit a mostly dark scene
+    pub fn bloom() -> Self {
+        PostEffect::Bloom {
+            threshold: 0.7,
+            intensity: 1.0,
+            radius: 8.0,
+        }
+    }
+
+    /// A subtle vignette
+    pub fn vignette() -> Self {
+        PostEffect::Vignette {
+            strength: 0.5,
+            radius: 0.6,
+            softness: 0.8,
+        }
+    }
+
+    pub fn color_grade(lut: Rc<Sheet>) -> Self {
+        PostEffect::ColorGrade { lut, strength: 1.0 }
+    }
+
+    /// A CRT look with scanlines every other pixel, which suits the text grid
+    pub fn crt() -> Self {
+        PostEffect::Crt {
+            scanline_intensity: 0.4,
+            curvature: 0.03,
+            grille_intensity: 0.15,
+            scanline_period: 2.0,
+        }
+    }
+
+    fn check(&self) -> Result<()> {
+        if let PostEffect::ColorGrade { lut, .. } = self {
+            let size = lut.height();
+            if size < 2 || lut.width() != size * size {
+                err!(
+                    "A color lookup table must be N*N x N pixels with N >= 2, got {}x{}",
+                    lut.width(),
+                    lut.height(),
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The shaders making up the builtin effects.
+/// Each effect is drawn with one or more passes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum PostPass {
+    Blur,
+    Bright,
+    Bloom,
+    Vignette,
+    Lut,
+    Crt,
+}
+
+impl PostPass {
+    fn spirv(self) -> &'static [u8] {
+        match self {
+            PostPass::Blur => shaders::BLUR_FRAG,
+            PostPass::Bright => shaders::BRIGHT_FRAG,
+            PostPass::Bloom => shaders::BLOOM_FRAG,
+            PostPass::Vignette => shaders::VIGNETTE_FRAG,
+            PostPass::Lut => shaders::LUT_FRAG,
+            PostPass::Crt => shaders::CRT_FRAG,
+        }
+    }
+}
+
+/// Uniform data of a pass, laid out to match `PostUniform`
+/// in the post-processing shaders (std140)
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct PostUniform {
+    texel_size: [f32; 2],
+    direction: [f32; 2],
+    params: [f32; 4],
+}
+
+unsafe impl bytemuck::Pod for PostUniform {}
+unsafe impl bytemuck::Zeroable for PostUniform {}
+
+/// The second texture a pass reads from, if any
+#[derive(Debug, PartialEq)]
+enum PassExtra {
+    None,
+    Texture(usize),
+    /// The lookup table of the `ColorGrade` effect at this index
+    Lut(usize),
+}
+
+struct PassStep {
+    pass: PostPass,
+    input: usize,
+    extra: PassExtra,
+    /// The intermediate texture to draw into, or None for the final view
+    output: Option<usize>,
+    direction: [f32; 2],
+    params: [f32; 4],
+}
+
+/// A screen sized texture passed between effects
+struct PostTexture {
+    #[allow(dead_code)]
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    bind_group: wgpu::BindGroup,
+}
+
+/// The post-processing effects of a Graphics2D and the
+/// GPU resources needed to apply them
+pub(super) struct PostChain {
+    effects: Vec<PostEffect>,
+    uniform_bind_group_layout: wgpu::BindGroupLayout,
+    pipeline_layout: wgpu::PipelineLayout,
+    vs_module: wgpu::ShaderModule,
+    sampler: wgpu::Sampler,
+    pipelines: HashMap<(PostPass, wgpu::TextureFormat), wgpu::RenderPipeline>,
+    textures: Vec<PostTexture>,
+    /// The lookup tables of the `ColorGrade` effects sampled with
+    /// `sampler`, by effect index
+    lut_bind_groups: HashMap<usize, wgpu::BindGroup>,
+    size: (u32, u32),
+}
+
+impl PostChain {
+    pub fn new(
+        device: &wgpu::Device,
+        texture_bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> Result<Self> {
+        let vs_data = wgpu::read_spirv(std::io::Cursor::new(shaders::POST_VERT))?;
+        let vs_module = device.create_shader_module(&vs_data);
+        let uniform_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                bindings: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::UniformBuffer { dynamic: false },
+                }],
+                label: Some("post_uniform_bind_group_layout"),
+            });
+        // Every pass gets an input, its parameters and a second texture
+        // (which is just the input again for passes that don't need one)
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            bind_group_layouts: &[
+                texture_bind_group_layout,
+                &uniform_bind_group_layout,
+                texture_bind_group_layout,
+            ],
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            // The intermediate textures have no mipmaps, and lookup
+            // tables are always read from their full size image
+            lod_min_clamp: 0.0,
+            lod_max_clamp: 0.0,
+            compare: wgpu::CompareFunction::Always,
+        });
+        Ok(Self {
+            effects: vec![],
+            uniform_bind_group_layout,
+            pipeline_layout,
+            vs_module,
+            sampler,
+            pipelines: HashMap::new(),
+            textures: vec![],
+            lut_bind_groups: HashMap::new(),
+            size: (0, 0),
+        })
+    }
+
+    pub fn effects(&self) -> &[PostEffect] {
+        &self.effects
+    }
+
+    pub fn set_effects(&mut self, effects: Vec<PostEffect>) -> Result<()> {
+        for effect in &effects {
+            effect.check()?;
+        }
+        self.effects = effects;
+        self.lut_bind_groups.clear();
+        Ok(())
+    }
+
+    pub fn clear_effects(&mut self) {
+        self.effects.clear();
+        self.lut_bind_groups.clear();
+    }
+
+    /// Whether the scene needs to be drawn into `scene_view`
+    /// instead of directly into the screen
+    pub fn is_active(&self) -> bool {
+        !self.effects.is_empty()
+    }
+
+    /// The texture the scene is drawn into before the effects are applied.
+    /// Only valid after `prepare`
+    pub fn scene_view(&self) -> &wgpu::TextureView {
+        &self.textures[0].view
+    }
+
+    /// Creates the intermediate textures for a screen of the given size
+    /// and the pipelines and bind groups for the current effects,
+    /// if they don't exist yet
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        texture_bind_group_layout: &wgpu::BindGroupLayout,
+        size: (u32, u32),
+        format: wgpu::TextureFormat,
+    ) -> Result<()> {
+        if !self.is_active() {
+            return Ok(());
+        }
+        if self.size != size || self.textures.is_empty() {
+            self.textures = (0..4)
+                .map(|_| self.create_texture(device, texture_bind_group_layout, size))
+                .collect();
+            self.size = size;
+        }
+        for (i, effect) in self.effects.iter().enumerate() {
+            if let PostEffect::ColorGrade { lut, .. } = effect {
+                if !self.lut_bind_groups.contains_key(&i) {
+                    let view = lut.texture().create_default_view();
+                    let bind_group = self.create_bind_group(
+                        device,
+                        texture_bind_group_layout,
+                        &view,
+                        "post_lut_bind_group",
+                    );
+                    self.lut_bind_groups.insert(i, bind_group);
+                }
+            }
+        }
+        let keys: Vec<_> = steps(&self.effects)
+            .iter()
+            .map(|step| match step.output {
+                Some(_) => (step.pass, OFFSCREEN_FORMAT),
+                None => (step.pass, format),
+            })
+            .collect();
+        for key in keys {
+            if !self.pipelines.contains_key(&key) {
+                let pipeline = self.create_pipeline(device, key.0, key.1)?;
+                self.pipelines.insert(key, pipeline);
+            }
+        }
+        Ok(())
+    }
+
+    fn create_texture(
+        &self,
+        device: &wgpu::Device,
+        texture_bind_group_layout: &wgpu::BindGroupLayout,
+        (width, height): (u32, u32),
+    ) -> PostTexture {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth: 1,
+            },
+            array_layer_count: 1,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: OFFSCREEN_FORMAT,
+            usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT | wgpu::TextureUsage::SAMPLED,
+            label: Some("post_texture"),
+        });
+        let view = texture.create_default_view();
+        let bind_group = self.create_bind_group(
+            device,
+            texture_bind_group_layout,
+            &view,
+            "post_texture_bind_group",
+        );
+        PostTexture {
+            texture,
+            view,
+            bind_group,
+        }
+    }
+
+    /// A bind group sampling the given view with the chain's sampler
+    fn create_bind_group(
+        &self,
+        device: &wgpu::Device,
+        texture_bind_group_layout: &wgpu::BindGroupLayout,
+        view: &wgpu::TextureView,
+        label: &str,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: texture_bind_group_layout,
+            bindings: &[
+                wgpu::Binding {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(view),
+                },
+                wgpu::Binding {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+            label: Some(label),
+        })
+    }
+
+    fn create_pipeline(
+        &self,
+        device: &wgpu::Device,
+        pass: PostPass,
+        format: wgpu::TextureFormat,
+    ) -> Result<wgpu::RenderPipeline> {
+        let fs_data = wgpu::read_spirv(std::io::Cursor::new(pass.spirv()))?;
+        let fs_module = device.create_shader_module(&fs_data);
+        Ok(
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                layout: &self.pipeline_layout,
+                vertex_stage: wgpu::ProgrammableStageDescriptor {
+                    module: &self.vs_module,
+                    entry_point: "main",
+                },
+                fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
+                    module: &fs_module,
+                    entry_point: "main",
+                }),
+                rasterization_state: Some(wgpu::RasterizationStateDescriptor {
+                    front_face: wgpu::FrontFace::Ccw,
+                    cull_mode: wgpu::CullMode::None,
+                    depth_bias: 0,
+                    depth_bias_slope_scale: 0.0,
+                    depth_bias_clamp: 0.0,
+                }),
+                color_states: &[BlendMode::Opaque.color_state(format)],
+                primitive_topology: wgpu::PrimitiveTopology::TriangleList,
+                depth_stencil_state: None,
+                vertex_state: wgpu::VertexStateDescriptor {
+                    index_format: wgpu::IndexFormat::Uint16,
+                    vertex_buffers: &[],
+                },
+                sample_count: 1,
+                sample_mask: !0,
+                alpha_to_coverage_enabled: false,
+            }),
+        )
+    }
+
+    /// Records the commands applying all effects to the scene
+    /// and drawing the result into the given view of the given format.
+    /// `prepare` must have been called first
+    pub fn encode(
+        &self,
+        device: &wgpu::Device,
+        view: &wgpu::TextureView,
+        format: wgpu::TextureFormat,
+    ) -> wgpu::CommandBuffer {
+        let (width, height) = self.size;
+        let texel_size = [1.0 / width as f32, 1.0 / height as f32];
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("post_encoder"),
+        });
+        for step in steps(&self.effects) {
+            let uniform = PostUniform {
+                texel_size,
+                direction: step.direction,
+                params: step.params,
+            };
+            let uniform_buffer = device.create_buffer_with_data(
+                bytemuck::cast_slice(&[uniform]),
+                wgpu::BufferUsage::UNIFORM,
+            );
+            let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: &self.uniform_bind_group_layout,
+                bindings: &[wgpu::Binding {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Buffer {
+                        buffer: &uniform_buffer,
+                        range: 0..std::mem::size_of::<PostUniform>() as wgpu::BufferAddress,
+                    },
+                }],
+                label: Some("post_uniform_bind_group"),
+            });
+            let input_bind_group = &self.textures[step.input].bind_group;
+            let extra_bind_group = match step.extra {
+                PassExtra::None => input_bind_group,
+                PassExtra::Texture(t) => &self.textures[t].bind_group,
+                PassExtra::Lut(i) => &self.lut_bind_groups[&i],
+            };
+            let (output_view, format) = match step.output {
+                Some(t) => (&self.textures[t].view, OFFSCREEN_FORMAT),
+                None => (view, format),
+            };
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+                    attachment: output_view,
+                    resolve_target: None,
+                    load_op: wgpu::LoadOp::Clear,
+                    store_op: wgpu::StoreOp::Store,
+                    clear_color: wgpu::Color {
+                        r: 0.0,
+                        g: 0.0,
+                        b: 0.0,
+                        a: 0.0,
+                    },
+                }],
+                depth_stencil_attachment: None,
+            });
+            render_pass.set_pipeline(&self.pipelines[&(step.pass, format)]);
+            render_pass.set_bind_group(0, input_bind_group, &[]);
+            render_pass.set_bind_group(1, &uniform_bind_group, &[]);
+            render_pass.set_bind_group(2, extra_bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+        encoder.finish()
+    }
+}
+
+/// Lays out the passes of all effects.
+///
+/// The scene stays untouched in the first texture, so that it can be
+/// kept for the next frame. The image is then passed around the other
+/// three: each effect reads from the one holding the image so far and
+/// uses two others as scratch space, ending in the second of them
+/// (or the final view)
+fn steps(effects: &[PostEffect]) -> Vec<PassStep> {
+    let mut steps = vec![];
+    let mut current = 0;
+    for (i, effect) in effects.iter().enumerate() {
+        let others: Vec<usize> = (1..4).filter(|&t| t != current).collect();
+        let (temp, next) = (others[0], others[1]);
+        let output = if i + 1 == effects.len() {
+            None
+        } else {
+            Some(next)
+        };
+        let step = |pass, input, extra, output, direction, params| PassStep {
+            pass,
+            input,
+            extra,
+            output,
+            direction,
+            params,
+        };
+        match effect {
+            PostEffect::Blur { radius } => {
+                let params = [*radius, 0.0, 0.0, 0.0];
+                steps.push(step(
+                    PostPass::Blur,
+                    current,
+                    PassExtra::None,
+                    Some(temp),
+                    [1.0, 0.0],
+                    params,
+                ));
+                steps.push(step(
+                    PostPass::Blur,
+                    temp,
+                    PassExtra::None,
+                    output,
+                    [0.0, 1.0],
+                    params,
+                ));
+            }
+            PostEffect::Bloom {
+                threshold,
+                intensity,
+                radius,
+            } => {
+                let blur_params = [*radius, 0.0, 0.0, 0.0];
+                steps.push(step(
+                    PostPass::Bright,
+                    current,
+                    PassExtra::None,
+                    Some(temp),
+                    [0.0, 0.0],
+                    [*threshold, 0.0, 0.0, 0.0],
+                ));
+                steps.push(step(
+                    PostPass::Blur,
+                    temp,
+                    PassExtra::None,
+                    Some(next),
+                    [1.0, 0.0],
+                    blur_params,
+                ));
+                steps.push(step(
+                    PostPass::Blur,
+                    next,
+                    PassExtra::None,
+                    Some(temp),
+                    [0.0, 1.0],
+                    blur_params,
+                ));
+                steps.push(step(
+                    PostPass::Bloom,
+                    temp,
+                    PassExtra::Texture(current),
+                    output,
+                    [0.0, 0.0],
+                    [*intensity, 0.0, 0.0, 0.0],
+                ));
+            }
+            PostEffect::Vignette {
+                strength,
+                radius,
+                softness,
+            } => {
+                steps.push(step(
+                    PostPass::Vignette,
+                    current,
+                    PassExtra::None,
+                    output,
+                    [0.0, 0.0],
+                    [*strength, *radius, *softness, 0.0],
+                ));
+            }
+            PostEffect::ColorGrade { lut, strength } => {
+                steps.push(step(
+                    PostPass::Lut,
+                    current,
+                    PassExtra::Lut(i),
+                    output,
+                    [0.0, 0.0],
+                    [lut.height() as f32, *strength, 0.0, 0.0],
+                ));
+            }
+            PostEffect::Crt {
+                scanline_intensity,
+                curvature,
+                grille_intensity,
+                scanline_period,
+            } => {
+                steps.push(step(
+                    PostPass::Crt,
+                    current,
+                    PassExtra::None,
+                    output,
+                    [0.0, 0.0],
+                    [
+                        *scanline_intensity,
+                        *curvature,
+                        *grille_intensity,
+                        *scanline_period,
+                    ],
+                ));
+            }
+        }
+        current = next;
+    }
+    steps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn all_effects(graphics: &mut Graphics2D) -> Vec<PostEffect> {
+        let lut = Sheet::color_lut(graphics, 2, |color| color).unwrap();
+        vec![
+            PostEffect::blur(2.0),
+            PostEffect::bloom(),
+            PostEffect::vignette(),
+            PostEffect::color_grade(lut),
+            PostEffect::crt(),
+        ]
+    }
+
+    fn check_steps(effects: &[PostEffect]) {
+        let steps = steps(effects);
+        let (last, rest) = steps.split_last().unwrap();
+        assert_eq!(last.output, None, "the final pass must target the view");
+        for step in rest {
+            assert!(
+                step.output.is_some(),
+                "only the final pass targets the view"
+            );
+        }
+        // The first texture holds the scene
+        let mut written = vec![0];
+        for step in &steps {
+            let mut reads = vec![step.input];
+            if let PassExtra::Texture(t) = step.extra {
+                reads.push(t);
+            }
+            for t in reads {
+                assert!(
+                    written.contains(&t),
+                    "texture {} is read before it's drawn",
+                    t
+                );
+                assert_ne!(
+                    Some(t),
+                    step.output,
+                    "a pass reads and writes texture {}",
+                    t
+                );
+            }
+            if let Some(t) = step.output {
+                assert_ne!(t, 0, "the scene is overwritten");
+                written.push(t);
+            }
+        }
+    }
+
+    #[test]
+    fn steps_of_effect_chains() {
+        let mut graphics = Graphics2D::new_software(4, 4);
+        let effects = all_effects(&mut graphics);
+        for a in &effects {
+            check_steps(std::slice::from_ref(a));
+            for b in &effects {
+                check_steps(&[a.clone(), b.clone()]);
+                for c in &effects {
+                    check_steps(&[a.clone(), b.clone(), c.clone()]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn color_grade_reads_its_own_lut() {
+        let mut graphics = Graphics2D::new_software(4, 4);
+        let effects = all_effects(&mut graphics);
+        let extras: Vec<_> = steps(&effects)
+            .into_iter()
+            .filter(|step| step.pass == PostPass::Lut)
+            .map(|step| step.extra)
+            .collect();
+        assert_eq!(extras, vec![PassExtra::Lut(3)]);
+    }
+}
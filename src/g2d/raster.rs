@@ -0,0 +1,452 @@
+use super::*;
+
+/// A frame being drawn by the software renderer, standing in for the
+/// render pass recorded by `Gpu::encode_batches`
+///
+/// Every sprite goes through the same steps as in `shader.vert` and
+/// `shader.frag`, and colors are blended in linear space like the GPU
+/// does when drawing into an sRGB texture. Results are close to, but not
+/// guaranteed to be bit for bit identical with, what the GPU produces.
+pub(super) struct Canvas {
+    width: u32,
+    height: u32,
+    pixels: Vec<[f32; 4]>,
+    depth: Vec<f32>,
+}
+
+impl Canvas {
+    /// Starts a frame of the given size that is either cleared to a color,
+    /// or starts from the RGBA bytes of the previous frame
+    pub fn new(width: u32, height: u32, clear_color: Option<Color>, previous: &[u8]) -> Self {
+        let len = (width * height) as usize;
+        let pixels = match clear_color {
+            Some(color) => vec![color.to_array(); len],
+            None => previous.chunks(4).map(decode_pixel).collect(),
+        };
+        Self {
+            width,
+            height,
+            pixels,
+            // Like the depth attachment, cleared at the start of every pass
+            depth: vec![1.0; len],
+        }
+    }
+
+    /// Draws the given batches in order (i.e. later batches are drawn
+    /// over earlier ones). The flag paired with each batch tells whether
+    /// it's viewed through the camera
+    pub fn draw_batches(&mut self, scale: Scaling, camera: Camera, batches: &[(&Batch, bool)]) {
+        let to_pixels =
+            Affine::scaling(self.width as f32 / scale[0], self.height as f32 / scale[1]);
+        let screen_view = Camera::identity(scale).transform(scale).then(to_pixels);
+        let camera_view = camera.transform(scale).then(to_pixels);
+        let camera_scissor = camera.scissor_rect(self.width, self.height);
+        for &(batch, follows_camera) in batches {
+            let (view, scissor) = if follows_camera {
+                (camera_view, camera_scissor)
+            } else {
+                (screen_view, [0, 0, self.width, self.height])
+            };
+            self.draw_batch(batch, view, scissor);
+        }
+    }
+
+    fn draw_batch(&mut self, batch: &Batch, view: Affine, scissor: [u32; 4]) {
+        let texture = batch.sheet().soft_texture();
+        let [pivot_x, pivot_y] = batch.pivot();
+        let [scale_x, scale_y] = batch.scale();
+        let [translate_x, translate_y] = batch.translation();
+        let batch_to_pixels = Affine::translation(-pivot_x, -pivot_y)
+            .then(Affine::scaling(scale_x, scale_y))
+            .then(Affine::rotation(batch.rotation()))
+            .then(Affine::translation(
+                pivot_x + translate_x,
+                pivot_y + translate_y,
+            ))
+            .then(view);
+        let [r, g, b, a] = batch.tint().to_array();
+        let tint = [r, g, b, a * batch.opacity()];
+        for instance in batch.flushed_instances() {
+            self.draw_instance(
+                instance,
+                &texture,
+                batch_to_pixels,
+                tint,
+                batch.blend_mode(),
+                scissor,
+            );
+        }
+    }
+
+    fn draw_instance(
+        &mut self,
+        instance: &Instance,
+        texture: &SoftTexture,
+        batch_to_pixels: Affine,
+        tint: [f32; 4],
+        blend_mode: BlendMode,
+        scissor: [u32; 4],
+    ) {
+        if !instance.visible() {
+            return;
+        }
+        // z is clamped when set, so this is always within the depth range
+        let depth = 0.5 - 0.5 * instance.z();
+        let [src_ul, src_lr] = instance.src_corners();
+        let [dst_ul, dst_lr] = instance.dst_corners();
+        let dst_center = [(dst_ul[0] + dst_lr[0]) / 2.0, (dst_ul[1] + dst_lr[1]) / 2.0];
+
+        // Maps a point of the unit square (the `positions` of the vertex
+        // shader) to the pixel it ends up at
+        let transform = Affine::new(
+            dst_lr[0] - dst_ul[0],
+            0.0,
+            0.0,
+            dst_lr[1] - dst_ul[1],
+            dst_ul[0],
+            dst_ul[1],
+        )
+        .then(Affine::rotation(instance.rotation()).around(dst_center))
+        .then(instance.transform().around(dst_ul))
+        .then(batch_to_pixels);
+
+        // Like the pipeline, mirrored quads are drawn too,
+        // but degenerate ones don't cover any pixel
+        let inverse = match transform.inverse() {
+            Some(inverse) => inverse,
+            None => return,
+        };
+
+        // Only visit the pixels that the quad could possibly cover
+        let corners: Vec<Point> = [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]]
+            .iter()
+            .map(|&corner| transform.apply(corner))
+            .collect();
+        let min_x = corners.iter().map(|p| p.x).fold(f32::INFINITY, f32::min);
+        let max_x = corners
+            .iter()
+            .map(|p| p.x)
+            .fold(f32::NEG_INFINITY, f32::max);
+        let min_y = corners.iter().map(|p| p.y).fold(f32::INFINITY, f32::min);
+        let max_y = corners
+            .iter()
+            .map(|p| p.y)
+            .fold(f32::NEG_INFINITY, f32::max);
+        let [scissor_x, scissor_y, scissor_width, scissor_height] = scissor;
+        let clamp = |v: f32, lo: u32, hi: u32| (v.max(lo as f32) as u32).min(hi);
+        let px_start = clamp(min_x.floor(), scissor_x, scissor_x + scissor_width);
+        let px_end = clamp(max_x.ceil(), scissor_x, scissor_x + scissor_width);
+        let py_start = clamp(min_y.floor(), scissor_y, scissor_y + scissor_height);
+        let py_end = clamp(max_y.ceil(), scissor_y, scissor_y + scissor_height);
+
+        let color_factor = instance.color_factor();
+        let tiling = instance.tiling();
+        for py in py_start..py_end {
+            for px in px_start..px_end {
+                let Point { x: nx, y: ny } = inverse.apply([px as f32 + 0.5, py as f32 + 0.5]);
+                if !(0.0..1.0).contains(&nx) || !(0.0..1.0).contains(&ny) {
+                    continue;
+                }
+                let index = (py * self.width + px) as usize;
+                // The depth test is LessEqual
+                if depth > self.depth[index] {
+                    continue;
+                }
+                let (nx, ny) = match tiling {
+                    Some(Tiling { repeat, offset }) => {
+                        let tx = nx * repeat[0] + offset[0];
+                        let ty = ny * repeat[1] + offset[1];
+                        (tx - tx.floor(), ty - ty.floor())
+                    }
+                    None => (nx, ny),
+                };
+                let u = src_ul[0] + nx * (src_lr[0] - src_ul[0]);
+                let v = src_ul[1] + ny * (src_lr[1] - src_ul[1]);
+                let texel = texture.sample(u, v);
+                let mut color = [0.0; 4];
+                for i in 0..4 {
+                    color[i] = texel[i] * color_factor[i] * tint[i];
+                }
+                // Like the fragment shader, fully transparent colors
+                // are discarded and don't write depth
+                if color[3] <= 0.0 {
+                    continue;
+                }
+                blend(&mut self.pixels[index], color, blend_mode);
+                if blend_mode.writes_depth() {
+                    self.depth[index] = depth;
+                }
+            }
+        }
+    }
+
+    /// The RGBA bytes of the frame, 4 bytes per pixel,
+    /// row by row starting from the upper-left corner
+    pub fn into_rgba(self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.pixels.len() * 4);
+        for pixel in self.pixels {
+            bytes.extend_from_slice(&encode_pixel(pixel));
+        }
+        bytes
+    }
+}
+
+/// Mirrors the pipeline's blend state for each `BlendMode`
+/// (see `BlendMode::color_state`)
+fn blend(dst: &mut [f32; 4], src: [f32; 4], mode: BlendMode) {
+    let a = src[3];
+    for (d, &s) in dst.iter_mut().zip(&src).take(3) {
+        *d = match mode {
+            BlendMode::Alpha => s * a + *d * (1.0 - a),
+            BlendMode::PremultipliedAlpha => s + *d * (1.0 - a),
+            BlendMode::Additive => s * a + *d,
+            BlendMode::Multiply => s * *d,
+            BlendMode::Screen => s + *d * (1.0 - s),
+            BlendMode::Opaque => s,
+        }
+        // The target can't hold values outside of [0, 1]
+        .clamp(0.0, 1.0);
+    }
+    match mode {
+        BlendMode::Alpha | BlendMode::Opaque => dst[3] = a,
+        BlendMode::PremultipliedAlpha => dst[3] = a + dst[3] * (1.0 - a),
+        BlendMode::Additive | BlendMode::Multiply | BlendMode::Screen => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: [u8; 4] = [255, 255, 255, 255];
+    const CLEAR: [u8; 4] = [0, 0, 0, 0];
+
+    fn render(graphics: &mut Graphics2D) -> Screenshot {
+        graphics.flush().unwrap();
+        graphics.force_render().unwrap();
+        graphics.screenshot().unwrap()
+    }
+
+    fn square_batch(graphics: &mut Graphics2D, color: [f32; 3], dst: [f32; 4], z: f32) -> Batch {
+        let sheet = Sheet::from_color(graphics, color).unwrap();
+        let desc = SpriteDesc {
+            z,
+            ..SpriteDesc::new(0, dst)
+        };
+        Batch::new(graphics, sheet, 1, 1, &[desc])
+    }
+
+    #[test]
+    fn clear_color() {
+        let mut graphics = Graphics2D::new_software(4, 3);
+        assert!(graphics.is_software());
+        graphics.set_clear_color([1.0, 0.0, 0.0, 1.0]);
+        let screenshot = render(&mut graphics);
+        assert_eq!((screenshot.width(), screenshot.height()), (4, 3));
+        for pixel in screenshot.rgba().chunks(4) {
+            assert_eq!(pixel, [255, 0, 0, 255]);
+        }
+    }
+
+    #[test]
+    fn set_pixel() {
+        let mut graphics = Graphics2D::new_software(4, 4);
+        graphics.set_pixel(1, 2, [1.0, 1.0, 1.0]).unwrap();
+        let screenshot = render(&mut graphics);
+        assert_eq!(screenshot.get_pixel(1, 2).unwrap(), WHITE);
+        assert_eq!(screenshot.get_pixel(2, 1).unwrap(), CLEAR);
+    }
+
+    #[test]
+    fn sprite_covers_its_dst() {
+        let mut graphics = Graphics2D::new_software(4, 4);
+        let batch = square_batch(&mut graphics, [1.0, 1.0, 1.0], [1.0, 1.0, 3.0, 3.0], 0.0);
+        graphics.set_batch(0, batch).unwrap();
+        let screenshot = render(&mut graphics);
+        for y in 0..4 {
+            for x in 0..4 {
+                let inside = (1..3).contains(&x) && (1..3).contains(&y);
+                let expected = if inside { WHITE } else { CLEAR };
+                assert_eq!(
+                    screenshot.get_pixel(x, y).unwrap(),
+                    expected,
+                    "at {}, {}",
+                    x,
+                    y
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn builtin_batches_are_drawn_over_every_slot() {
+        let mut graphics = Graphics2D::new_software(2, 2);
+        graphics.set_pixel(0, 0, [1.0, 0.0, 0.0]).unwrap();
+        for slot in 0..SLOT_LIMIT {
+            // Not even the largest z puts a user sprite in front of the builtins
+            let z = if slot == 0 { 1.0 } else { 0.0 };
+            let batch = square_batch(&mut graphics, [1.0, 1.0, 1.0], [0.0, 0.0, 2.0, 2.0], z);
+            graphics.set_batch(slot, batch).unwrap();
+        }
+        let screenshot = render(&mut graphics);
+        assert_eq!(screenshot.get_pixel(0, 0).unwrap(), [255, 0, 0, 255]);
+        assert_eq!(screenshot.get_pixel(1, 1).unwrap(), WHITE);
+    }
+
+    #[test]
+    fn higher_z_is_in_front_regardless_of_slot() {
+        let mut graphics = Graphics2D::new_software(2, 2);
+        let red = square_batch(&mut graphics, [1.0, 0.0, 0.0], [0.0, 0.0, 2.0, 2.0], 0.0);
+        let green = square_batch(&mut graphics, [0.0, 1.0, 0.0], [0.0, 0.0, 2.0, 2.0], 0.5);
+        // Without z, the red batch would be drawn on top
+        graphics.set_batch(0, red).unwrap();
+        graphics.set_batch(1, green).unwrap();
+        let screenshot = render(&mut graphics);
+        assert_eq!(screenshot.get_pixel(0, 0).unwrap(), [0, 255, 0, 255]);
+    }
+
+    #[test]
+    fn z_outside_of_the_depth_range_is_clamped() {
+        let mut graphics = Graphics2D::new_software(2, 2);
+        let red = square_batch(&mut graphics, [1.0, 0.0, 0.0], [0.0, 0.0, 2.0, 2.0], 1.0);
+        let green = square_batch(&mut graphics, [0.0, 1.0, 0.0], [0.0, 0.0, 2.0, 2.0], 5.0);
+        graphics.set_batch(0, red).unwrap();
+        graphics.set_batch(1, green).unwrap();
+        // Both are at z = 1, so the red batch is drawn on top
+        let screenshot = render(&mut graphics);
+        assert_eq!(screenshot.get_pixel(0, 0).unwrap(), [255, 0, 0, 255]);
+
+        let mut graphics = Graphics2D::new_software(2, 2);
+        let batch = square_batch(&mut graphics, [1.0, 1.0, 1.0], [0.0, 0.0, 2.0, 2.0], -5.0);
+        graphics.set_batch(0, batch).unwrap();
+        let screenshot = render(&mut graphics);
+        assert_eq!(screenshot.get_pixel(0, 0).unwrap(), WHITE);
+    }
+
+    #[test]
+    fn additive_sprites_dont_hide_lower_z() {
+        let mut graphics = Graphics2D::new_software(2, 2);
+        let mut glow = square_batch(&mut graphics, [0.0, 0.0, 1.0], [0.0, 0.0, 2.0, 2.0], 0.5);
+        glow.set_blend_mode(BlendMode::Additive);
+        let red = square_batch(&mut graphics, [1.0, 0.0, 0.0], [0.0, 0.0, 2.0, 2.0], 0.0);
+        // Slot 1 is drawn before slot 0
+        graphics.set_batch(1, glow).unwrap();
+        graphics.set_batch(0, red).unwrap();
+        let screenshot = render(&mut graphics);
+        assert_eq!(screenshot.get_pixel(0, 0).unwrap(), [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn translucent_pixels_hide_lower_z_drawn_after_them() {
+        let mut graphics = Graphics2D::new_software(2, 2);
+        let sheet = Sheet::from_color(&mut graphics, [1.0, 1.0, 1.0]).unwrap();
+        let front = SpriteDesc {
+            z: 0.5,
+            color: [1.0, 1.0, 1.0, 0.5].into(),
+            ..SpriteDesc::new(0, [0.0, 0.0, 2.0, 2.0])
+        };
+        let front = Batch::new(&mut graphics, sheet, 1, 1, &[front]);
+        let back = square_batch(&mut graphics, [1.0, 0.0, 0.0], [0.0, 0.0, 2.0, 2.0], 0.0);
+        // Slot 1 is drawn before slot 0
+        graphics.set_batch(1, front).unwrap();
+        graphics.set_batch(0, back).unwrap();
+        let screenshot = render(&mut graphics);
+        let [r, g, b, _] = screenshot.get_pixel(0, 0).unwrap();
+        assert!(r == g && g == b, "the red sprite shows through");
+    }
+
+    #[test]
+    fn mirrored_sprite_is_drawn() {
+        let mut graphics = Graphics2D::new_software(4, 4);
+        let mut batch = square_batch(&mut graphics, [1.0, 1.0, 1.0], [0.0, 0.0, 2.0, 2.0], 0.0);
+        // Mirrored around the left edge of its dst, then moved back in view
+        batch
+            .get(0)
+            .transform(Affine::scaling(-1.0, 1.0).then(Affine::translation(4.0, 0.0)));
+        graphics.set_batch(0, batch).unwrap();
+        let screenshot = render(&mut graphics);
+        assert_eq!(screenshot.get_pixel(0, 0).unwrap(), CLEAR);
+        assert_eq!(screenshot.get_pixel(2, 0).unwrap(), WHITE);
+        assert_eq!(screenshot.get_pixel(3, 1).unwrap(), WHITE);
+    }
+
+    #[test]
+    fn camera_moves_user_batches_only() {
+        let mut graphics = Graphics2D::new_software(4, 4);
+        let batch = square_batch(&mut graphics, [1.0, 1.0, 1.0], [0.0, 0.0, 1.0, 1.0], 0.0);
+        graphics.set_batch(0, batch).unwrap();
+        graphics.set_pixel(3, 3, [1.0, 1.0, 1.0]).unwrap();
+        // The world origin is shown at the center of the frame
+        graphics.set_camera(Camera::new([0.0, 0.0])).unwrap();
+        let screenshot = render(&mut graphics);
+        assert_eq!(screenshot.get_pixel(0, 0).unwrap(), CLEAR);
+        assert_eq!(screenshot.get_pixel(2, 2).unwrap(), WHITE);
+        assert_eq!(screenshot.get_pixel(3, 3).unwrap(), WHITE);
+    }
+
+    #[test]
+    fn unflushed_changes_are_not_drawn() {
+        let mut graphics = Graphics2D::new_software(2, 2);
+        let batch = square_batch(&mut graphics, [1.0, 1.0, 1.0], [0.0, 0.0, 2.0, 2.0], 0.0);
+        graphics.set_batch(0, batch).unwrap();
+        render(&mut graphics);
+        let batch = graphics.batch_mut(0).unwrap();
+        batch.get(0).color([0.0, 0.0, 0.0]);
+        batch.push(&SpriteDesc::new(0, [0.0, 0.0, 1.0, 1.0]));
+        graphics.force_render().unwrap();
+        assert_eq!(
+            graphics.screenshot().unwrap().get_pixel(1, 1).unwrap(),
+            WHITE
+        );
+        let screenshot = render(&mut graphics);
+        assert_eq!(screenshot.get_pixel(1, 1).unwrap(), [0, 0, 0, 255]);
+        assert_eq!(screenshot.get_pixel(0, 0).unwrap(), WHITE);
+    }
+
+    #[test]
+    fn draw_char_changes_the_frame() {
+        let mut graphics = Graphics2D::new_software(64, 32);
+        graphics.init_text_grid(4).unwrap();
+        let before = render(&mut graphics).into_rgba();
+        graphics.draw_char(0, 0, '#').unwrap();
+        let after = render(&mut graphics).into_rgba();
+        assert_ne!(before, after);
+    }
+
+    #[test]
+    fn free_text_reuses_its_batch() {
+        let mut graphics = Graphics2D::new_software(64, 32);
+        let blank = render(&mut graphics).into_rgba();
+        graphics
+            .draw_text_at([0.0, 0.0], "#", 16.0, [1.0, 1.0, 1.0])
+            .unwrap();
+        assert_ne!(render(&mut graphics).into_rgba(), blank);
+        graphics.clear_text();
+        graphics
+            .draw_text_at([0.0, 0.0], "##", 16.0, [1.0, 1.0, 1.0])
+            .unwrap();
+        render(&mut graphics);
+        let batch = graphics.builtin_batches[BUILTIN_FREE_TEXT]
+            .as_ref()
+            .unwrap();
+        assert_eq!(batch.len(), 2);
+        let sheet = batch.sheet().clone();
+        graphics.clear_text();
+        assert_eq!(render(&mut graphics).into_rgba(), blank);
+        let batch = graphics.builtin_batches[BUILTIN_FREE_TEXT]
+            .as_ref()
+            .unwrap();
+        assert!(batch.is_empty());
+        assert!(Rc::ptr_eq(batch.sheet(), &sheet));
+    }
+
+    #[test]
+    fn gpu_only_features_are_rejected() {
+        let mut graphics = Graphics2D::new_software(2, 2);
+        assert!(RenderTarget::new(&mut graphics, 2, 2).is_err());
+        assert!(graphics
+            .set_post_effects(vec![PostEffect::vignette()])
+            .is_err());
+        assert!(graphics.post_effects().is_empty());
+    }
+}
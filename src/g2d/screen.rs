@@ -0,0 +1,241 @@
+use super::*;
+
+/// wgpu requires the rows of a texture to buffer copy to be
+/// aligned to this many bytes
+const COPY_BYTES_PER_ROW_ALIGNMENT: u32 = 256;
+
+/// Format used for offscreen targets.
+/// RGBA ordering lets `read_pixels` return the bytes as is.
+pub(super) const OFFSCREEN_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+/// Format used for the window's swap chain
+pub(super) const WINDOW_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Bgra8UnormSrgb;
+
+/// Format of the depth buffer used for sorting sprites by z
+pub(super) const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+/// Where the output of `force_render` ends up
+pub(super) enum Screen {
+    Window {
+        surface: wgpu::Surface,
+        sc_desc: wgpu::SwapChainDescriptor,
+        swap_chain: wgpu::SwapChain,
+    },
+    Offscreen {
+        texture: wgpu::Texture,
+        view: wgpu::TextureView,
+        width: u32,
+        height: u32,
+    },
+}
+
+/// Where a render pass draws its batches, and how they are viewed
+pub(super) struct PassTarget<'a> {
+    pub view: &'a wgpu::TextureView,
+    pub depth_view: &'a wgpu::TextureView,
+    pub format: wgpu::TextureFormat,
+    pub size: (u32, u32),
+    pub scale: Scaling,
+    pub camera: Camera,
+    pub clear_color: Option<Color>,
+}
+
+/// The texture a single call to `force_render` draws into
+pub(super) enum Frame {
+    Window(wgpu::SwapChainOutput),
+    Offscreen,
+}
+
+impl Screen {
+    pub fn window(device: &wgpu::Device, surface: wgpu::Surface, width: u32, height: u32) -> Self {
+        let sc_desc = wgpu::SwapChainDescriptor {
+            usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT,
+            format: WINDOW_FORMAT,
+            width,
+            height,
+            present_mode: wgpu::PresentMode::Fifo,
+        };
+        let swap_chain = device.create_swap_chain(&surface, &sc_desc);
+        Screen::Window {
+            surface,
+            sc_desc,
+            swap_chain,
+        }
+    }
+
+    pub fn offscreen(device: &wgpu::Device, width: u32, height: u32) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth: 1,
+            },
+            array_layer_count: 1,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: OFFSCREEN_FORMAT,
+            // COPY_SRC is needed so that the rendered image can be
+            // copied into a buffer and read back by read_pixels
+            usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT | wgpu::TextureUsage::COPY_SRC,
+            label: Some("offscreen_texture"),
+        });
+        let view = texture.create_default_view();
+        Screen::Offscreen {
+            texture,
+            view,
+            width,
+            height,
+        }
+    }
+
+    pub fn format(&self) -> wgpu::TextureFormat {
+        match self {
+            Screen::Window { sc_desc, .. } => sc_desc.format,
+            Screen::Offscreen { .. } => OFFSCREEN_FORMAT,
+        }
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        match self {
+            Screen::Window {
+                surface,
+                sc_desc,
+                swap_chain,
+            } => {
+                sc_desc.width = width;
+                sc_desc.height = height;
+                *swap_chain = device.create_swap_chain(surface, sc_desc);
+            }
+            Screen::Offscreen { .. } => {
+                *self = Screen::offscreen(device, width, height);
+            }
+        }
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        match self {
+            Screen::Window { sc_desc, .. } => (sc_desc.width, sc_desc.height),
+            Screen::Offscreen { width, height, .. } => (*width, *height),
+        }
+    }
+
+    pub fn next_frame(&mut self) -> Frame {
+        match self {
+            Screen::Window { swap_chain, .. } => Frame::Window(
+                swap_chain
+                    .get_next_texture()
+                    .expect("Timeout getting next texture"),
+            ),
+            Screen::Offscreen { .. } => Frame::Offscreen,
+        }
+    }
+
+    pub fn view<'a>(&'a self, frame: &'a Frame) -> &'a wgpu::TextureView {
+        match (self, frame) {
+            (_, Frame::Window(output)) => &output.view,
+            (Screen::Offscreen { view, .. }, Frame::Offscreen) => view,
+            (Screen::Window { .. }, Frame::Offscreen) => {
+                panic!("Offscreen frame used with a window Screen")
+            }
+        }
+    }
+}
+
+/// Creates a texture the same size and format as the screen that
+/// can be rendered into and then copied from
+pub(super) fn create_capture_texture(device: &wgpu::Device, screen: &Screen) -> wgpu::Texture {
+    let (width, height) = screen.size();
+    device.create_texture(&wgpu::TextureDescriptor {
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth: 1,
+        },
+        array_layer_count: 1,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: screen.format(),
+        usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT | wgpu::TextureUsage::COPY_SRC,
+        label: Some("capture_texture"),
+    })
+}
+
+/// Creates a depth buffer for a frame of the given size
+pub(super) fn create_depth_texture(
+    device: &wgpu::Device,
+    width: u32,
+    height: u32,
+) -> (wgpu::Texture, wgpu::TextureView) {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth: 1,
+        },
+        array_layer_count: 1,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: DEPTH_FORMAT,
+        usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT,
+        label: Some("depth_texture"),
+    });
+    let view = texture.create_default_view();
+    (texture, view)
+}
+
+/// Copies the contents of a texture into tightly packed bytes
+/// (4 bytes per pixel, row by row starting from the upper-left corner)
+///
+/// The device needs to be polled for this future to complete
+pub(super) async fn read_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+    width: u32,
+    height: u32,
+) -> Result<Vec<u8>> {
+    let unpadded_bytes_per_row = 4 * width;
+    let padded_bytes_per_row = unpadded_bytes_per_row.div_ceil(COPY_BYTES_PER_ROW_ALIGNMENT)
+        * COPY_BYTES_PER_ROW_ALIGNMENT;
+    let size = (padded_bytes_per_row * height) as wgpu::BufferAddress;
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("read_texture_buffer"),
+        size,
+        usage: wgpu::BufferUsage::COPY_DST | wgpu::BufferUsage::MAP_READ,
+    });
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("read_texture_encoder"),
+    });
+    encoder.copy_texture_to_buffer(
+        wgpu::TextureCopyView {
+            texture,
+            mip_level: 0,
+            array_layer: 0,
+            origin: wgpu::Origin3d::ZERO,
+        },
+        wgpu::BufferCopyView {
+            buffer: &buffer,
+            offset: 0,
+            bytes_per_row: padded_bytes_per_row,
+            rows_per_image: height,
+        },
+        wgpu::Extent3d {
+            width,
+            height,
+            depth: 1,
+        },
+    );
+    queue.submit(&[encoder.finish()]);
+
+    let mapping = buffer.map_read(0, size).await?;
+    let padded = mapping.as_slice();
+    let mut pixels = Vec::with_capacity((unpadded_bytes_per_row * height) as usize);
+    for row in 0..height as usize {
+        let start = row * padded_bytes_per_row as usize;
+        pixels.extend_from_slice(&padded[start..start + unpadded_bytes_per_row as usize]);
+    }
+    Ok(pixels)
+}
@@ -0,0 +1,87 @@
+use super::*;
+use std::path::Path;
+
+/// An RGBA image captured from a Graphics2D
+pub struct Screenshot {
+    width: u32,
+    height: u32,
+    rgba: Vec<u8>,
+}
+
+impl Screenshot {
+    pub(super) fn new(width: u32, height: u32, rgba: Vec<u8>) -> Self {
+        assert_eq!((width * height * 4) as usize, rgba.len());
+        Self {
+            width,
+            height,
+            rgba,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// RGBA bytes, 4 bytes per pixel, row by row starting
+    /// from the upper-left corner
+    pub fn rgba(&self) -> &[u8] {
+        &self.rgba
+    }
+
+    pub fn into_rgba(self) -> Vec<u8> {
+        self.rgba
+    }
+
+    /// Returns the color of the pixel at the given location,
+    /// or None if it's outside of the image
+    pub fn get_pixel(&self, x: u32, y: u32) -> Option<[u8; 4]> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        let i = ((y * self.width + x) * 4) as usize;
+        Some([
+            self.rgba[i],
+            self.rgba[i + 1],
+            self.rgba[i + 2],
+            self.rgba[i + 3],
+        ])
+    }
+
+    /// Encodes the image as PNG
+    pub fn to_png_bytes(&self) -> Result<Vec<u8>> {
+        use image::ImageEncoder;
+        let mut bytes = Vec::new();
+        image::png::PngEncoder::new(&mut bytes).write_image(
+            &self.rgba,
+            self.width,
+            self.height,
+            image::ColorType::Rgba8,
+        )?;
+        Ok(bytes)
+    }
+
+    /// Writes the image to the given path as PNG
+    pub fn save_png<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        std::fs::write(path, self.to_png_bytes()?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn get_pixel() {
+        let rgba = (0..2 * 3 * 4).map(|i| i as u8).collect();
+        let screenshot = Screenshot::new(2, 3, rgba);
+        assert_eq!(screenshot.get_pixel(0, 0), Some([0, 1, 2, 3]));
+        assert_eq!(screenshot.get_pixel(1, 2), Some([20, 21, 22, 23]));
+        assert_eq!(screenshot.get_pixel(2, 0), None);
+        assert_eq!(screenshot.get_pixel(0, 3), None);
+    }
+}
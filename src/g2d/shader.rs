@@ -0,0 +1,344 @@
+use super::*;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+
+static NEXT_SHADER_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// A user supplied fragment shader that replaces the builtin one
+/// for the batches it is attached to (see `Batch::set_shader`)
+///
+/// The shader is given as SPIR-V (or GLSL with the `glsl` cargo feature),
+/// and must have the following interface (in GLSL):
+///
+/// ```glsl
+/// #version 450
+///
+/// // Texture coordinates on the batch's sheet
+/// layout(location=0) in vec2 v_tex_coords;
+///
+/// // The sprite's color multiplied by the batch's tint and opacity
+/// layout(location=1) in vec4 v_color_factor;
+///
+/// layout(location=0) out vec4 f_color;
+///
+/// // The batch's sheet
+/// layout(set = 0, binding = 0) uniform texture2D t_diffuse;
+/// layout(set = 0, binding = 1) uniform sampler s_diffuse;
+///
+/// // Set with `Batch::set_shader_params`, using std140 layout
+/// // (e.g. a vec4 or vec2 must start at a multiple of 4 or 2 floats)
+/// layout(set = 3, binding = 0) uniform Params {
+///     float u_time;
+/// };
+///
+/// void main() {
+///     f_color = texture(sampler2D(t_diffuse, s_diffuse), v_tex_coords) * v_color_factor;
+///     // Fully transparent fragments should be discarded so that they
+///     // don't hide sprites with a lower z
+///     if (f_color.a <= 0.0) {
+///         discard;
+///     }
+/// }
+/// ```
+///
+/// Tiled sprites are not wrapped by custom shaders; `v_tex_coords` is
+/// always the stretched coordinate.
+///
+/// The render pipelines created for a shader are kept by `Graphics2D`
+/// until it is dropped, even after the shader itself is dropped,
+/// so shaders should be loaded once rather than every frame.
+pub struct Shader {
+    id: usize,
+    module: wgpu::ShaderModule,
+}
+
+impl Shader {
+    /// Loads a fragment shader from SPIR-V bytes
+    ///
+    /// Returns an error if the bytes are not SPIR-V for a fragment shader
+    /// whose inputs and bindings match the interface above. Inputs may
+    /// have fewer components than documented, and the params uniform
+    /// may be left out.
+    ///
+    /// Custom shaders are not supported by the software renderer
+    pub fn from_spirv(graphics: &mut Graphics2D, bytes: &[u8]) -> Result<Rc<Self>> {
+        let gpu = match graphics.renderer.gpu() {
+            Some(gpu) => gpu,
+            None => {
+                err!("Custom shaders are not supported by the software renderer");
+            }
+        };
+        if bytes.len() < 20 || !bytes.len().is_multiple_of(4) {
+            err!("Fragment shader is not valid SPIR-V (bad length)");
+        }
+        let data = wgpu::read_spirv(std::io::Cursor::new(bytes))?;
+        check_interface(&data)?;
+        let module = gpu.device.create_shader_module(&data);
+        Ok(Rc::new(Self {
+            id: NEXT_SHADER_ID.fetch_add(1, Ordering::Relaxed),
+            module,
+        }))
+    }
+
+    /// Compiles a fragment shader from GLSL source
+    ///
+    /// Only available with the `glsl` cargo feature
+    #[cfg(feature = "glsl")]
+    pub fn from_glsl(graphics: &mut Graphics2D, source: &str) -> Result<Rc<Self>> {
+        let spirv = shaders::compile_glsl(source, shaders::ShaderStage::Fragment)?;
+        Self::from_spirv(graphics, &spirv)
+    }
+
+    pub(super) fn id(&self) -> usize {
+        self.id
+    }
+
+    pub(super) fn module(&self) -> &wgpu::ShaderModule {
+        &self.module
+    }
+}
+
+const OP_ENTRY_POINT: u32 = 15;
+const OP_TYPE_FLOAT: u32 = 22;
+const OP_TYPE_VECTOR: u32 = 23;
+const OP_TYPE_IMAGE: u32 = 25;
+const OP_TYPE_SAMPLER: u32 = 26;
+const OP_TYPE_STRUCT: u32 = 30;
+const OP_TYPE_POINTER: u32 = 32;
+const OP_VARIABLE: u32 = 59;
+const OP_DECORATE: u32 = 71;
+
+const EXECUTION_MODEL_FRAGMENT: u32 = 4;
+
+const DECORATION_BUILTIN: u32 = 11;
+const DECORATION_LOCATION: u32 = 30;
+const DECORATION_BINDING: u32 = 33;
+const DECORATION_DESCRIPTOR_SET: u32 = 34;
+
+const STORAGE_UNIFORM_CONSTANT: u32 = 0;
+const STORAGE_INPUT: u32 = 1;
+const STORAGE_UNIFORM: u32 = 2;
+const STORAGE_OUTPUT: u32 = 3;
+const STORAGE_WORKGROUP: u32 = 4;
+const STORAGE_PRIVATE: u32 = 6;
+const STORAGE_FUNCTION: u32 = 7;
+
+/// Checks that SPIR-V words describe a fragment shader matching the
+/// interface documented on `Shader`
+fn check_interface(words: &[u32]) -> Result<()> {
+    let mut fragment = false;
+    let mut locations = HashMap::new();
+    let mut builtins = vec![];
+    let mut sets = HashMap::new();
+    let mut bindings = HashMap::new();
+    // Result id to opcode and operands for the types that are checked
+    let mut types: HashMap<u32, (u32, &[u32])> = HashMap::new();
+    // Pointer type, result id and storage class
+    let mut variables = vec![];
+
+    // Skip the header (magic number, version, generator, bound and schema)
+    let mut i = 5;
+    while i < words.len() {
+        let count = (words[i] >> 16) as usize;
+        let opcode = words[i] & 0xffff;
+        if count == 0 || i + count > words.len() {
+            err!("Fragment shader is not valid SPIR-V (truncated instruction)");
+        }
+        let operands = &words[i + 1..i + count];
+        match (opcode, operands) {
+            (OP_ENTRY_POINT, [model, ..]) => fragment |= *model == EXECUTION_MODEL_FRAGMENT,
+            (OP_DECORATE, [target, DECORATION_LOCATION, location, ..]) => {
+                locations.insert(*target, *location);
+            }
+            (OP_DECORATE, [target, DECORATION_BUILTIN, ..]) => builtins.push(*target),
+            (OP_DECORATE, [target, DECORATION_DESCRIPTOR_SET, set, ..]) => {
+                sets.insert(*target, *set);
+            }
+            (OP_DECORATE, [target, DECORATION_BINDING, binding, ..]) => {
+                bindings.insert(*target, *binding);
+            }
+            (OP_TYPE_FLOAT, [id, ..])
+            | (OP_TYPE_VECTOR, [id, ..])
+            | (OP_TYPE_IMAGE, [id, ..])
+            | (OP_TYPE_SAMPLER, [id, ..])
+            | (OP_TYPE_STRUCT, [id, ..])
+            | (OP_TYPE_POINTER, [id, ..]) => {
+                types.insert(*id, (opcode, operands));
+            }
+            (OP_VARIABLE, [pointer, id, storage, ..]) => variables.push((*pointer, *id, *storage)),
+            _ => {}
+        }
+        i += count;
+    }
+    if !fragment {
+        err!("Shader is not a fragment shader");
+    }
+
+    // The type a variable of the given pointer type points to
+    let pointee = |pointer: u32| match types.get(&pointer) {
+        Some((OP_TYPE_POINTER, [_, _, pointee, ..])) => types.get(pointee).copied(),
+        _ => None,
+    };
+    // The number of floats in a float scalar or vector type
+    let float_components = |ty: Option<(u32, &[u32])>| match ty {
+        Some((OP_TYPE_FLOAT, _)) => Some(1),
+        Some((OP_TYPE_VECTOR, [_, component, count, ..])) => match types.get(component) {
+            Some((OP_TYPE_FLOAT, _)) => Some(*count),
+            _ => None,
+        },
+        _ => None,
+    };
+
+    for (pointer, id, storage) in variables {
+        match storage {
+            STORAGE_INPUT => {
+                if builtins.contains(&id) {
+                    continue;
+                }
+                let (name, max_components) = match locations.get(&id) {
+                    Some(0) => ("v_tex_coords", 2),
+                    Some(1) => ("v_color_factor", 4),
+                    Some(location) => {
+                        err!(
+                            "Fragment shader reads an input at location {}, which isn't \
+                             part of the interface",
+                            location
+                        );
+                    }
+                    None => {
+                        err!("Fragment shader has an input without a location");
+                    }
+                };
+                match float_components(pointee(pointer)) {
+                    Some(components) if components <= max_components => {}
+                    _ => {
+                        err!(
+                            "Fragment shader input {} must be a float vector \
+                             of at most {} components",
+                            name,
+                            max_components
+                        );
+                    }
+                }
+            }
+            STORAGE_OUTPUT | STORAGE_FUNCTION | STORAGE_PRIVATE | STORAGE_WORKGROUP => {}
+            _ => {
+                let set = sets.get(&id).copied().unwrap_or(0);
+                let binding = bindings.get(&id).copied().unwrap_or(0);
+                let ty = pointee(pointer).map(|(opcode, _)| opcode);
+                let (expected_storage, expected_type, expected) = match (set, binding) {
+                    (0, 0) => (
+                        STORAGE_UNIFORM_CONSTANT,
+                        OP_TYPE_IMAGE,
+                        "the sheet's texture",
+                    ),
+                    (0, 1) => (
+                        STORAGE_UNIFORM_CONSTANT,
+                        OP_TYPE_SAMPLER,
+                        "the sheet's sampler",
+                    ),
+                    (3, 0) => (STORAGE_UNIFORM, OP_TYPE_STRUCT, "a uniform block"),
+                    _ => {
+                        err!(
+                            "Fragment shader binds a resource at set {}, binding {}, \
+                             which isn't part of the interface",
+                            set,
+                            binding
+                        );
+                    }
+                };
+                if (storage, ty) != (expected_storage, Some(expected_type)) {
+                    err!(
+                        "Fragment shader binding at set {}, binding {} must be {}",
+                        set,
+                        binding,
+                        expected
+                    );
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Identifies a render pipeline in Graphics2D's cache
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(super) struct PipelineKey {
+    /// The format of the texture the pipeline renders into
+    pub format: wgpu::TextureFormat,
+
+    pub blend_mode: BlendMode,
+
+    /// The id of the custom fragment shader, if any
+    pub shader: Option<usize>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(bytes: &[u8]) -> Vec<u32> {
+        wgpu::read_spirv(std::io::Cursor::new(bytes)).unwrap()
+    }
+
+    fn error(bytes: &[u8]) -> String {
+        check_interface(&words(bytes)).unwrap_err().to_string()
+    }
+
+    /// The CRT post effect, with its params moved from set 1 to set 3
+    fn custom_crt() -> Vec<u32> {
+        let mut words = words(shaders::CRT_FRAG);
+        for i in 0..words.len() - 3 {
+            if words[i] == (4 << 16 | OP_DECORATE)
+                && words[i + 2] == DECORATION_DESCRIPTOR_SET
+                && words[i + 3] == 1
+            {
+                words[i + 3] = 3;
+            }
+        }
+        words
+    }
+
+    #[test]
+    fn matching_interface() {
+        check_interface(&custom_crt()).unwrap();
+    }
+
+    #[test]
+    fn wrong_binding_type() {
+        // Swap the bindings of the texture and the sampler
+        let mut words = custom_crt();
+        for i in 0..words.len() - 3 {
+            if words[i] == (4 << 16 | OP_DECORATE) && words[i + 2] == DECORATION_BINDING {
+                words[i + 3] ^= 1;
+            }
+        }
+        let error = check_interface(&words).unwrap_err().to_string();
+        assert!(error.contains("must be the sheet's sampler"), "{}", error);
+    }
+
+    #[test]
+    fn extra_input() {
+        // The builtin shader also reads the tiling inputs
+        assert!(error(shaders::FRAG).contains("location 2"));
+    }
+
+    #[test]
+    fn extra_binding() {
+        assert!(error(shaders::CRT_FRAG).contains("set 1, binding 0"));
+    }
+
+    #[test]
+    fn vertex_shader() {
+        assert!(error(shaders::VERT).contains("not a fragment shader"));
+    }
+
+    #[test]
+    fn truncated() {
+        // An instruction announcing more words than there are left
+        let mut words = words(shaders::LUT_FRAG);
+        words.push(4 << 16 | OP_DECORATE);
+        let error = check_interface(&words).unwrap_err().to_string();
+        assert!(error.contains("truncated"));
+    }
+}
@@ -1,8 +1,113 @@
 use super::*;
 
+/// How a sheet's pixels are interpolated when drawn
+/// at a different size than the original
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterMode {
+    /// Uses the closest pixel, keeping pixel art sharp
+    Nearest,
+
+    /// Blends the surrounding pixels
+    Linear,
+}
+
+/// What is sampled outside of the sheet (normalized source
+/// coordinates less than 0 or greater than 1)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressMode {
+    /// Repeats the pixels at the edge of the sheet
+    ClampToEdge,
+
+    /// Tiles the sheet
+    Repeat,
+
+    /// Tiles the sheet, mirroring every other copy
+    MirrorRepeat,
+}
+
+/// Sampling options for a Sheet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SheetOptions {
+    pub filter: FilterMode,
+    pub address_mode: AddressMode,
+
+    /// Whether to generate smaller copies of the sheet (mip levels),
+    /// used when the sheet is drawn smaller than its original size
+    /// to avoid shimmering
+    pub mipmaps: bool,
+}
+
+impl SheetOptions {
+    /// Linear filtering, clamped to edge and without mipmaps.
+    /// These are the options used by the Sheet constructors that
+    /// don't take any options
+    pub fn new() -> Self {
+        Self {
+            filter: FilterMode::Linear,
+            address_mode: AddressMode::ClampToEdge,
+            mipmaps: false,
+        }
+    }
+
+    /// Nearest neighbor filtering, for pixel art
+    pub fn pixel_art() -> Self {
+        Self::new().filter(FilterMode::Nearest)
+    }
+
+    pub fn filter(mut self, filter: FilterMode) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    pub fn address_mode(mut self, address_mode: AddressMode) -> Self {
+        self.address_mode = address_mode;
+        self
+    }
+
+    pub fn mipmaps(mut self, mipmaps: bool) -> Self {
+        self.mipmaps = mipmaps;
+        self
+    }
+
+    fn wgpu_filter(&self) -> wgpu::FilterMode {
+        match self.filter {
+            FilterMode::Nearest => wgpu::FilterMode::Nearest,
+            FilterMode::Linear => wgpu::FilterMode::Linear,
+        }
+    }
+
+    fn wgpu_address_mode(&self) -> wgpu::AddressMode {
+        match self.address_mode {
+            AddressMode::ClampToEdge => wgpu::AddressMode::ClampToEdge,
+            AddressMode::Repeat => wgpu::AddressMode::Repeat,
+            AddressMode::MirrorRepeat => wgpu::AddressMode::MirrorRepeat,
+        }
+    }
+}
+
+impl Default for SheetOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// An image loaded in GPU memory ready to be used with a Batch
-pub(super) struct Sheet {
-    bind_group: wgpu::BindGroup,
+///
+/// With the software renderer (see `Graphics2D::new_software`),
+/// the image is kept in memory instead
+pub struct Sheet {
+    texture: SheetTexture,
+    width: u32,
+    height: u32,
+    options: SheetOptions,
+}
+
+enum SheetTexture {
+    Gpu {
+        texture: wgpu::Texture,
+        bind_group: wgpu::BindGroup,
+    },
+    Soft(RefCell<SoftTexture>),
 }
 
 impl Sheet {
@@ -11,15 +116,27 @@ impl Sheet {
     /// The bytes are interpreted by passing the bytes to the
     /// `load_from_memory` function from the `image` crate
     pub fn from_bytes(state: &mut Graphics2D, diffuse_bytes: &[u8]) -> Result<Rc<Self>> {
+        Self::from_bytes_with_options(state, diffuse_bytes, SheetOptions::new())
+    }
+
+    /// Like `from_bytes`, but with the given sampling options
+    pub fn from_bytes_with_options(
+        state: &mut Graphics2D,
+        diffuse_bytes: &[u8],
+        options: SheetOptions,
+    ) -> Result<Rc<Self>> {
         let diffuse_image = image::load_from_memory(diffuse_bytes)?;
-        let diffuse_rgba = diffuse_image.to_rgba();
-        Self::from_rbga_image(state, diffuse_rgba)
+        let diffuse_rgba = diffuse_image.to_rgba8();
+        Self::from_rbga_image(state, diffuse_rgba, options)
     }
 
+    /// Creates a 1x1 sprite sheet of a single color
     pub fn from_color<C: Into<Color>>(state: &mut Graphics2D, color: C) -> Result<Rc<Self>> {
         Self::from_colors::<C, Vec<C>>(state, 1, 1, vec![color])
     }
 
+    /// Creates a sprite sheet from `width * height` colors listed
+    /// row by row starting from the upper-left corner
     pub fn from_colors<C, V>(
         state: &mut Graphics2D,
         width: u32,
@@ -39,35 +156,92 @@ impl Sheet {
         Self::from_rgba_bytes(state, width, height, pixels)
     }
 
+    /// Creates a sprite sheet from raw RGBA bytes, 4 bytes per pixel
     pub fn from_rgba_bytes(
         state: &mut Graphics2D,
         width: u32,
         height: u32,
         bytes: Vec<u8>,
+    ) -> Result<Rc<Self>> {
+        Self::from_rgba_bytes_with_options(state, width, height, bytes, SheetOptions::new())
+    }
+
+    /// Like `from_rgba_bytes`, but with the given sampling options
+    pub fn from_rgba_bytes_with_options(
+        state: &mut Graphics2D,
+        width: u32,
+        height: u32,
+        bytes: Vec<u8>,
+        options: SheetOptions,
     ) -> Result<Rc<Self>> {
         let rgba = match image::RgbaImage::from_raw(width, height, bytes) {
             Some(img) => img,
-            None => err!("Failed to create image from rgba bytes for Sheet"),
+            None => {
+                err!("Failed to create image from rgba bytes for Sheet");
+            }
         };
-        Self::from_rbga_image(state, rgba)
+        Self::from_rbga_image(state, rgba, options)
+    }
+
+    /// Creates a color lookup table for `PostEffect::ColorGrade` by
+    /// calling `f` on `size` evenly spaced values of each channel.
+    ///
+    /// Both the colors passed to `f` and the ones it returns are in
+    /// sRGB space, like colors picked in an image editor
+    pub fn color_lut<F>(state: &mut Graphics2D, size: u32, f: F) -> Result<Rc<Self>>
+    where
+        F: Fn(Color) -> Color,
+    {
+        if size < 2 {
+            err!("A color lookup table needs at least 2 entries per channel");
+        }
+        let max = (size - 1) as f32;
+        let mut colors = Vec::with_capacity((size * size * size) as usize);
+        for g in 0..size {
+            for b in 0..size {
+                for r in 0..size {
+                    colors.push(f([r as f32 / max, g as f32 / max, b as f32 / max].into()));
+                }
+            }
+        }
+        Self::from_colors(state, size * size, size, colors)
     }
 
     /// This method is private because we don't want to expose the `image` crate
     /// as a dependency.
     /// The version of `image` we use might not match with the version
     /// that the binary crate uses.
-    fn from_rbga_image(state: &mut Graphics2D, diffuse_rgba: image::RgbaImage) -> Result<Rc<Self>> {
-        let device = &state.device;
-        let texture_bind_group_layout = &state.texture_bind_group_layout;
-        let queue = &state.queue;
-
+    fn from_rbga_image(
+        state: &mut Graphics2D,
+        diffuse_rgba: image::RgbaImage,
+        options: SheetOptions,
+    ) -> Result<Rc<Self>> {
         let dimensions = diffuse_rgba.dimensions();
-        let size = wgpu::Extent3d {
-            width: dimensions.0,
-            height: dimensions.1,
-            depth: 1,
+        let gpu = match state.renderer.gpu() {
+            Some(gpu) => gpu,
+            None => {
+                let texture = SoftTexture::from_rgba_bytes(
+                    dimensions.0,
+                    dimensions.1,
+                    &diffuse_rgba,
+                    options,
+                );
+                return Ok(Rc::new(Self {
+                    texture: SheetTexture::Soft(RefCell::new(texture)),
+                    width: dimensions.0,
+                    height: dimensions.1,
+                    options,
+                }));
+            }
+        };
+        let device = &gpu.device;
+        let queue = &gpu.queue;
+
+        let mip_levels = if options.mipmaps {
+            mip_chain(diffuse_rgba)
+        } else {
+            vec![diffuse_rgba]
         };
-        let buffer = device.create_buffer_with_data(&diffuse_rgba, wgpu::BufferUsage::COPY_SRC);
         let diffuse_texture = device.create_texture(&wgpu::TextureDescriptor {
             // All textures are stored as 3d, we represent our 2d texture
             // by setting depth to 1.
@@ -79,7 +253,7 @@ impl Sheet {
             // You can store multiple textures of the same size in one
             // Sheet object
             array_layer_count: 1,
-            mip_level_count: 1, // We'll talk about this a little later
+            mip_level_count: mip_levels.len() as u32,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
             format: wgpu::TextureFormat::Rgba8UnormSrgb,
@@ -93,40 +267,106 @@ impl Sheet {
                 label: Some("texture_buffer_copy_encoder"),
             });
 
-            encoder.copy_buffer_to_texture(
-                wgpu::BufferCopyView {
-                    buffer: &buffer,
-                    offset: 0,
-                    bytes_per_row: 4 * dimensions.0,
-                    rows_per_image: dimensions.1,
-                },
-                wgpu::TextureCopyView {
-                    texture: &diffuse_texture,
-                    mip_level: 0,
-                    array_layer: 0,
-                    origin: wgpu::Origin3d::ZERO,
-                },
-                size,
-            );
+            for (mip_level, level) in mip_levels.iter().enumerate() {
+                let (width, height) = level.dimensions();
+                let buffer = device.create_buffer_with_data(level, wgpu::BufferUsage::COPY_SRC);
+                encoder.copy_buffer_to_texture(
+                    wgpu::BufferCopyView {
+                        buffer: &buffer,
+                        offset: 0,
+                        bytes_per_row: 4 * width,
+                        rows_per_image: height,
+                    },
+                    wgpu::TextureCopyView {
+                        texture: &diffuse_texture,
+                        mip_level: mip_level as u32,
+                        array_layer: 0,
+                        origin: wgpu::Origin3d::ZERO,
+                    },
+                    wgpu::Extent3d {
+                        width,
+                        height,
+                        depth: 1,
+                    },
+                );
+            }
 
             queue.submit(&[encoder.finish()]);
         }
+        Ok(Self::from_texture(
+            gpu,
+            diffuse_texture,
+            dimensions.0,
+            dimensions.1,
+            options,
+        ))
+    }
+
+    /// Creates a sheet that a `RenderTarget` draws into.
+    /// Mipmaps are never generated for it, whatever the options say
+    pub(super) fn render_target(
+        gpu: &Gpu,
+        width: u32,
+        height: u32,
+        options: SheetOptions,
+    ) -> Result<Rc<Self>> {
+        if width == 0 || height == 0 {
+            err!("Render targets must be at least 1x1");
+        }
+        let texture = gpu.device.create_texture(&wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth: 1,
+            },
+            array_layer_count: 1,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: OFFSCREEN_FORMAT,
+            // OUTPUT_ATTACHMENT lets batches be drawn into the texture,
+            // and COPY_SRC lets it be read back like an offscreen screen
+            usage: wgpu::TextureUsage::SAMPLED
+                | wgpu::TextureUsage::OUTPUT_ATTACHMENT
+                | wgpu::TextureUsage::COPY_SRC
+                | wgpu::TextureUsage::COPY_DST,
+            label: Some("render_target_texture"),
+        });
+        Ok(Self::from_texture(
+            gpu,
+            texture,
+            width,
+            height,
+            options.mipmaps(false),
+        ))
+    }
+
+    /// Creates the sampler and bind group for an already filled texture
+    fn from_texture(
+        gpu: &Gpu,
+        diffuse_texture: wgpu::Texture,
+        width: u32,
+        height: u32,
+        options: SheetOptions,
+    ) -> Rc<Self> {
+        let device = &gpu.device;
+        let texture_bind_group_layout = &gpu.texture_bind_group_layout;
         let diffuse_texture_view = diffuse_texture.create_default_view();
 
         let diffuse_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Linear,
-            mipmap_filter: wgpu::FilterMode::Nearest,
+            address_mode_u: options.wgpu_address_mode(),
+            address_mode_v: options.wgpu_address_mode(),
+            address_mode_w: options.wgpu_address_mode(),
+            mag_filter: options.wgpu_filter(),
+            min_filter: options.wgpu_filter(),
+            mipmap_filter: options.wgpu_filter(),
             lod_min_clamp: -100.0,
             lod_max_clamp: 100.0,
             compare: wgpu::CompareFunction::Always,
         });
 
         let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &texture_bind_group_layout,
+            layout: texture_bind_group_layout,
             bindings: &[
                 wgpu::Binding {
                     binding: 0,
@@ -139,10 +379,178 @@ impl Sheet {
             ],
             label: Some("diffuse_bind_group"),
         });
-        Ok(Rc::new(Self { bind_group }))
+        Rc::new(Self {
+            texture: SheetTexture::Gpu {
+                texture: diffuse_texture,
+                bind_group,
+            },
+            width,
+            height,
+            options,
+        })
+    }
+
+    /// Width of the sheet in pixels
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Height of the sheet in pixels
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The sampling options the sheet was created with
+    pub fn options(&self) -> SheetOptions {
+        self.options
+    }
+
+    /// Overwrites a region of the sheet with the given RGBA bytes.
+    ///
+    /// Only the full size mip level is updated, so this is only meant
+    /// for sheets created without mipmaps (like glyph atlases); the
+    /// smaller levels of other sheets would keep the old pixels
+    pub(super) fn write_rgba(
+        &self,
+        gpu: Option<&Gpu>,
+        origin: [u32; 2],
+        size: [u32; 2],
+        bytes: &[u8],
+    ) {
+        debug_assert!(
+            !self.options.mipmaps,
+            "write_rgba would leave the sheet's smaller mip levels stale"
+        );
+        let texture = match &self.texture {
+            SheetTexture::Gpu { texture, .. } => texture,
+            SheetTexture::Soft(texture) => {
+                texture.borrow_mut().write_rgba(origin, size, bytes);
+                return;
+            }
+        };
+        let gpu = gpu.expect("Sheet created on the GPU written to by the software renderer");
+        let (device, queue) = (&gpu.device, &gpu.queue);
+        let [x, y] = origin;
+        let [width, height] = size;
+        assert_eq!((width * height * 4) as usize, bytes.len());
+        let buffer = device.create_buffer_with_data(bytes, wgpu::BufferUsage::COPY_SRC);
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("texture_region_copy_encoder"),
+        });
+        encoder.copy_buffer_to_texture(
+            wgpu::BufferCopyView {
+                buffer: &buffer,
+                offset: 0,
+                bytes_per_row: 4 * width,
+                rows_per_image: height,
+            },
+            wgpu::TextureCopyView {
+                texture,
+                mip_level: 0,
+                array_layer: 0,
+                origin: wgpu::Origin3d { x, y, z: 0 },
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth: 1,
+            },
+        );
+        queue.submit(&[encoder.finish()]);
+    }
+
+    pub(super) fn texture(&self) -> &wgpu::Texture {
+        match &self.texture {
+            SheetTexture::Gpu { texture, .. } => texture,
+            SheetTexture::Soft(_) => {
+                panic!("Sheet created for the software renderer drawn on the GPU")
+            }
+        }
+    }
+
+    pub(super) fn bind_group(&self) -> &wgpu::BindGroup {
+        match &self.texture {
+            SheetTexture::Gpu { bind_group, .. } => bind_group,
+            SheetTexture::Soft(_) => {
+                panic!("Sheet created for the software renderer drawn on the GPU")
+            }
+        }
+    }
+
+    pub(super) fn soft_texture(&self) -> std::cell::Ref<'_, SoftTexture> {
+        match &self.texture {
+            SheetTexture::Soft(texture) => texture.borrow(),
+            SheetTexture::Gpu { .. } => {
+                panic!("Sheet created on the GPU drawn by the software renderer")
+            }
+        }
+    }
+}
+
+/// Returns the image followed by successively halved copies of it,
+/// down to 1x1
+fn mip_chain(image: image::RgbaImage) -> Vec<image::RgbaImage> {
+    let mut levels = vec![image];
+    loop {
+        let (width, height) = levels.last().unwrap().dimensions();
+        if width == 1 && height == 1 {
+            break;
+        }
+        let next = image::imageops::resize(
+            levels.last().unwrap(),
+            (width / 2).max(1),
+            (height / 2).max(1),
+            image::imageops::FilterType::Triangle,
+        );
+        levels.push(next);
+    }
+    levels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn options_map_to_the_sampler() {
+        let options = SheetOptions::pixel_art().address_mode(AddressMode::MirrorRepeat);
+        assert_eq!(options.wgpu_filter(), wgpu::FilterMode::Nearest);
+        assert_eq!(options.wgpu_address_mode(), wgpu::AddressMode::MirrorRepeat);
+        let options = SheetOptions::default().address_mode(AddressMode::Repeat);
+        assert_eq!(options.wgpu_filter(), wgpu::FilterMode::Linear);
+        assert_eq!(options.wgpu_address_mode(), wgpu::AddressMode::Repeat);
+        assert_eq!(
+            SheetOptions::new().wgpu_address_mode(),
+            wgpu::AddressMode::ClampToEdge
+        );
+        assert!(!SheetOptions::new().mipmaps);
+    }
+
+    #[test]
+    fn mip_chain_halves_down_to_1x1() {
+        let image = image::RgbaImage::from_pixel(5, 3, image::Rgba([10, 20, 30, 255]));
+        let levels = mip_chain(image);
+        let sizes: Vec<_> = levels.iter().map(|level| level.dimensions()).collect();
+        assert_eq!(sizes, vec![(5, 3), (2, 1), (1, 1)]);
+        for level in &levels {
+            assert!(level.pixels().all(|p| p.0 == [10, 20, 30, 255]));
+        }
+        let levels = mip_chain(image::RgbaImage::new(1, 1));
+        assert_eq!(levels.len(), 1);
     }
 
-    pub fn bind_group(&self) -> &wgpu::BindGroup {
-        &self.bind_group
+    #[test]
+    fn software_sheets_keep_their_options() {
+        let mut graphics = Graphics2D::new_software(1, 1);
+        let options = SheetOptions::pixel_art().address_mode(AddressMode::Repeat);
+        let bytes = vec![0, 0, 0, 255, 255, 255, 255, 255];
+        let sheet =
+            Sheet::from_rgba_bytes_with_options(&mut graphics, 2, 1, bytes, options).unwrap();
+        assert_eq!(sheet.options(), options);
+        // Repeating wraps the left edge around to the white texel
+        assert_eq!(
+            sheet.soft_texture().sample(-0.25, 0.5),
+            [1.0, 1.0, 1.0, 1.0]
+        );
     }
 }
@@ -0,0 +1,205 @@
+use super::*;
+
+/// The pixels of a Sheet created for the software renderer
+///
+/// Pixels are stored in linear space, as the GPU sees them
+/// when sampling from an sRGB texture
+pub(super) struct SoftTexture {
+    width: u32,
+    height: u32,
+    pixels: Vec<[f32; 4]>,
+    options: SheetOptions,
+}
+
+impl SoftTexture {
+    /// Decodes RGBA bytes (4 bytes per pixel, sRGB encoded)
+    pub fn from_rgba_bytes(width: u32, height: u32, bytes: &[u8], options: SheetOptions) -> Self {
+        assert_eq!((width * height * 4) as usize, bytes.len());
+        Self {
+            width,
+            height,
+            pixels: bytes.chunks(4).map(decode_pixel).collect(),
+            options,
+        }
+    }
+
+    /// Overwrites a region with the given RGBA bytes
+    pub fn write_rgba(&mut self, origin: [u32; 2], size: [u32; 2], bytes: &[u8]) {
+        let [x0, y0] = origin;
+        let [width, height] = size;
+        assert_eq!((width * height * 4) as usize, bytes.len());
+        for (row, line) in bytes.chunks((width * 4) as usize).enumerate() {
+            let start = ((y0 + row as u32) * self.width + x0) as usize;
+            for (pixel, rgba) in self.pixels[start..start + width as usize]
+                .iter_mut()
+                .zip(line.chunks(4))
+            {
+                *pixel = decode_pixel(rgba);
+            }
+        }
+    }
+
+    fn texel(&self, x: i64, y: i64) -> [f32; 4] {
+        let x = self.wrap(x, self.width as i64);
+        let y = self.wrap(y, self.height as i64);
+        self.pixels[y * self.width as usize + x]
+    }
+
+    /// Maps a texel coordinate outside of the sheet
+    /// according to the address mode
+    fn wrap(&self, i: i64, size: i64) -> usize {
+        let i = match self.options.address_mode {
+            AddressMode::ClampToEdge => i.max(0).min(size - 1),
+            AddressMode::Repeat => i.rem_euclid(size),
+            AddressMode::MirrorRepeat => {
+                let i = i.rem_euclid(2 * size);
+                if i >= size {
+                    2 * size - 1 - i
+                } else {
+                    i
+                }
+            }
+        };
+        i as usize
+    }
+
+    /// Samples the texture at normalized coordinates the same way
+    /// the sheet's sampler does.
+    ///
+    /// Mipmaps are not simulated; the full size image is always sampled
+    pub fn sample(&self, u: f32, v: f32) -> [f32; 4] {
+        if self.options.filter == FilterMode::Nearest {
+            let x = (u * self.width as f32).floor() as i64;
+            let y = (v * self.height as f32).floor() as i64;
+            return self.texel(x, y);
+        }
+        let x = u * self.width as f32 - 0.5;
+        let y = v * self.height as f32 - 0.5;
+        let x0 = x.floor();
+        let y0 = y.floor();
+        let fx = x - x0;
+        let fy = y - y0;
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        let a = self.texel(x0, y0);
+        let b = self.texel(x0 + 1, y0);
+        let c = self.texel(x0, y0 + 1);
+        let d = self.texel(x0 + 1, y0 + 1);
+        let mut out = [0.0; 4];
+        for i in 0..4 {
+            let top = a[i] * (1.0 - fx) + b[i] * fx;
+            let bottom = c[i] * (1.0 - fx) + d[i] * fx;
+            out[i] = top * (1.0 - fy) + bottom * fy;
+        }
+        out
+    }
+}
+
+/// sRGB encoded RGBA bytes to linear intensities, as done by the
+/// GPU when reading from an `Rgba8UnormSrgb` texture
+pub(super) fn decode_pixel(rgba: &[u8]) -> [f32; 4] {
+    [
+        srgb_to_linear(rgba[0]),
+        srgb_to_linear(rgba[1]),
+        srgb_to_linear(rgba[2]),
+        rgba[3] as f32 / 255.0,
+    ]
+}
+
+/// Linear intensities to sRGB encoded RGBA bytes, as done by the
+/// GPU when writing to an `*Srgb` texture
+pub(super) fn encode_pixel(pixel: [f32; 4]) -> [u8; 4] {
+    let [r, g, b, a] = pixel;
+    [
+        linear_to_srgb(r),
+        linear_to_srgb(g),
+        linear_to_srgb(b),
+        (a.clamp(0.0, 1.0) * 255.0).round() as u8,
+    ]
+}
+
+fn srgb_to_linear(x: u8) -> f32 {
+    let x = x as f32 / 255.0;
+    if x <= 0.04045 {
+        x / 12.92
+    } else {
+        ((x + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(x: f32) -> u8 {
+    let x = x.clamp(0.0, 1.0);
+    let x = if x <= 0.0031308 {
+        x * 12.92
+    } else {
+        1.055 * x.powf(1.0 / 2.4) - 0.055
+    };
+    (x * 255.0).round() as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A black texel followed by a white one
+    fn texture(filter: FilterMode, address_mode: AddressMode) -> SoftTexture {
+        let options = SheetOptions::new()
+            .filter(filter)
+            .address_mode(address_mode);
+        SoftTexture::from_rgba_bytes(2, 1, &[0, 0, 0, 255, 255, 255, 255, 255], options)
+    }
+
+    fn red(texture: &SoftTexture, u: f32) -> f32 {
+        texture.sample(u, 0.5)[0]
+    }
+
+    #[test]
+    fn nearest_samples_the_texel_under_the_point() {
+        let texture = texture(FilterMode::Nearest, AddressMode::ClampToEdge);
+        assert_eq!(red(&texture, 0.25), 0.0);
+        assert_eq!(red(&texture, 0.49), 0.0);
+        assert_eq!(red(&texture, 0.5), 1.0);
+        assert_eq!(red(&texture, 0.75), 1.0);
+    }
+
+    #[test]
+    fn linear_blends_between_texel_centers() {
+        let texture = texture(FilterMode::Linear, AddressMode::ClampToEdge);
+        assert_eq!(red(&texture, 0.25), 0.0);
+        assert_eq!(red(&texture, 0.5), 0.5);
+        assert_eq!(red(&texture, 0.75), 1.0);
+        // Past the texel centers, the edge texels are repeated
+        assert_eq!(red(&texture, 0.0), 0.0);
+        assert_eq!(red(&texture, 1.0), 1.0);
+    }
+
+    #[test]
+    fn address_modes() {
+        let clamp = texture(FilterMode::Nearest, AddressMode::ClampToEdge);
+        let repeat = texture(FilterMode::Nearest, AddressMode::Repeat);
+        let mirror = texture(FilterMode::Nearest, AddressMode::MirrorRepeat);
+        // [u, clamp, repeat, mirror]
+        let expected = [
+            [-0.75, 0.0, 0.0, 1.0],
+            [-0.25, 0.0, 1.0, 0.0],
+            [1.25, 1.0, 0.0, 1.0],
+            [1.75, 1.0, 1.0, 0.0],
+        ];
+        for &[u, c, r, m] in &expected {
+            assert_eq!(red(&clamp, u), c, "clamp at {}", u);
+            assert_eq!(red(&repeat, u), r, "repeat at {}", u);
+            assert_eq!(red(&mirror, u), m, "mirror at {}", u);
+        }
+        // Linear filtering wraps too: at the edge, a repeated texture
+        // blends with the texel on the other side
+        let repeat = texture(FilterMode::Linear, AddressMode::Repeat);
+        assert_eq!(red(&repeat, 0.0), 0.5);
+    }
+
+    #[test]
+    fn write_rgba_replaces_a_region() {
+        let mut texture = texture(FilterMode::Nearest, AddressMode::ClampToEdge);
+        texture.write_rgba([1, 0], [1, 1], &[0, 0, 0, 0]);
+        assert_eq!(texture.sample(0.75, 0.5), [0.0, 0.0, 0.0, 0.0]);
+        assert_eq!(texture.sample(0.25, 0.5), [0.0, 0.0, 0.0, 1.0]);
+    }
+}
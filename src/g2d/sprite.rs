@@ -1,15 +1,124 @@
 use super::*;
 
-pub(super) struct SpriteDesc {
+/// Describes the initial state of a sprite when creating a Batch
+#[derive(Debug, Clone, Copy)]
+pub struct SpriteDesc {
     /// Index into the batch/sheet indicating which rectangle of the
     /// source sheet to use as image
     pub src: usize,
 
+    /// Rectangle of the source sheet to use as image, in normalized
+    /// coordinates (between 0 and 1). Overrides `src` if set
+    pub src_rect: Option<Rect>,
+
+    /// Whether to mirror the image [horizontally, vertically]
+    pub flip: [bool; 2],
+
     /// Rectangle in the output to draw to
     pub dst: Rect,
 
+    /// Clockwise rotation in radians around the center of `dst`
     pub rotate: f32,
 
     /// The color factor to apply to this sprite
     pub color: Color,
+
+    /// Depth of the sprite between -1.0 and 1.0 (values outside of
+    /// this range are clamped)
+    ///
+    /// Sprites with a higher z are drawn in front of sprites with
+    /// a lower z, even if they are in different batches. Sprites with
+    /// the same z are drawn in batch and buffer order.
+    ///
+    /// Sprites are not sorted by z, so this is only exact for opaque
+    /// pixels: a partially transparent pixel hides the sprites with a
+    /// lower z that are drawn after it instead of blending over them.
+    /// Overlapping translucent sprites should use the same z and rely
+    /// on the drawing order instead. Sprites in batches with other
+    /// blend modes than `Alpha` and `Opaque` don't hide anything
+    /// (see `BlendMode`)
+    pub z: f32,
+
+    /// Whether the sprite is drawn at all
+    pub visible: bool,
+
+    /// Applied after `rotate`, relative to the upper-left corner of `dst`.
+    ///
+    /// e.g. to rotate around the middle of the bottom edge of a sprite
+    /// (like a character's feet), use
+    /// `Affine::rotation(theta).around([width / 2.0, height])`.
+    ///
+    /// A negative scale mirrors the sprite
+    pub transform: Affine,
+
+    /// If set, the source rectangle is repeated across `dst`
+    /// instead of being stretched over it
+    pub tiling: Option<Tiling>,
+}
+
+/// How a sprite's source rectangle repeats across its destination
+///
+/// Any source rectangle can be tiled, including a single cell
+/// of a larger sheet
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tiling {
+    /// How many times the source is repeated [horizontally, vertically].
+    /// Fractional counts show part of the last copy
+    pub repeat: [f32; 2],
+
+    /// Scrolls the tiles, in units of one tile.
+    /// Animating this moves the contents of the sprite while the sprite
+    /// itself stays in place, e.g. for parallax backgrounds or water
+    pub offset: [f32; 2],
+}
+
+impl Tiling {
+    pub fn new(repeat: [f32; 2]) -> Self {
+        Self {
+            repeat,
+            offset: [0.0, 0.0],
+        }
+    }
+
+    pub fn offset(mut self, offset: [f32; 2]) -> Self {
+        self.offset = offset;
+        self
+    }
+}
+
+impl SpriteDesc {
+    /// Creates a sprite description with no rotation and
+    /// a white (i.e. identity) color factor
+    pub fn new<R: Into<Rect>>(src: usize, dst: R) -> Self {
+        Self {
+            src,
+            src_rect: None,
+            flip: [false, false],
+            dst: dst.into(),
+            rotate: 0.0,
+            color: [1.0, 1.0, 1.0, 1.0].into(),
+            z: 0.0,
+            visible: true,
+            transform: Affine::IDENTITY,
+            tiling: None,
+        }
+    }
+
+    /// Like `new`, but uses an arbitrary rectangle of the sheet (in
+    /// normalized coordinates) instead of a cell of the batch's grid
+    pub fn from_src_rect<R1: Into<Rect>, R2: Into<Rect>>(src_rect: R1, dst: R2) -> Self {
+        Self {
+            src_rect: Some(src_rect.into()),
+            ..Self::new(0, dst)
+        }
+    }
+
+    /// Returns the normalized source rectangle for a batch whose
+    /// sheet is divided into a grid of `nrows x ncols` cells
+    pub(crate) fn src_rect_in(&self, nrows: usize, ncols: usize) -> Rect {
+        match self.src_rect {
+            Some(rect) => rect,
+            None => src_index_to_rect(nrows, ncols, self.src),
+        }
+    }
 }
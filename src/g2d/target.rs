@@ -0,0 +1,149 @@
+use super::*;
+
+/// An offscreen texture that batches can be drawn into with
+/// `Graphics2D::render_to_target`, and whose contents can then be
+/// drawn by another batch through `sheet`.
+///
+/// Like `Graphics2D`, a render target has a scale (defaulting to its size
+/// in pixels), a camera for batches that follow it, and a clear color.
+///
+/// A batch cannot be drawn into the target whose sheet it uses.
+/// Render targets are not available with the software renderer
+pub struct RenderTarget {
+    sheet: Rc<Sheet>,
+    view: wgpu::TextureView,
+    #[allow(dead_code)]
+    depth_texture: wgpu::Texture,
+    depth_view: wgpu::TextureView,
+    scale: Scaling,
+    camera: Option<Camera>,
+    clear_color: Option<Color>,
+}
+
+impl RenderTarget {
+    /// Creates a render target of `width x height` pixels
+    pub fn new(graphics: &mut Graphics2D, width: u32, height: u32) -> Result<Self> {
+        Self::with_options(graphics, width, height, SheetOptions::new())
+    }
+
+    /// Like `new`, but the target's sheet is sampled with the given options.
+    ///
+    /// Mipmaps are not generated for render targets
+    pub fn with_options(
+        graphics: &mut Graphics2D,
+        width: u32,
+        height: u32,
+        options: SheetOptions,
+    ) -> Result<Self> {
+        let gpu = match graphics.renderer.gpu() {
+            Some(gpu) => gpu,
+            None => {
+                err!("Render targets are not supported by the software renderer");
+            }
+        };
+        let sheet = Sheet::render_target(gpu, width, height, options)?;
+        let view = sheet.texture().create_default_view();
+        let (depth_texture, depth_view) = create_depth_texture(&gpu.device, width, height);
+        Ok(Self {
+            sheet,
+            view,
+            depth_texture,
+            depth_view,
+            scale: [width as f32, height as f32],
+            camera: None,
+            clear_color: Some([0.0, 0.0, 0.0, 0.0].into()),
+        })
+    }
+
+    /// The sheet holding what was drawn into this target,
+    /// to be used with `Batch::new`
+    pub fn sheet(&self) -> &Rc<Sheet> {
+        &self.sheet
+    }
+
+    /// Width of the target in pixels
+    pub fn width(&self) -> u32 {
+        self.sheet.width()
+    }
+
+    /// Height of the target in pixels
+    pub fn height(&self) -> u32 {
+        self.sheet.height()
+    }
+
+    /// See `Graphics2D::scale`
+    pub fn scale(&self) -> Scaling {
+        self.scale
+    }
+
+    /// See `Graphics2D::set_scale`
+    pub fn set_scale(&mut self, scale: Scaling) {
+        self.scale = scale;
+    }
+
+    /// See `Graphics2D::camera`
+    pub fn camera(&self) -> Camera {
+        match self.camera {
+            Some(camera) => camera,
+            None => Camera::identity(self.scale),
+        }
+    }
+
+    /// Sets the camera that batches following the camera are viewed
+    /// through when drawn into this target.
+    /// Fails if the camera's zoom isn't positive
+    pub fn set_camera(&mut self, camera: Camera) -> Result<()> {
+        camera.check()?;
+        self.camera = Some(camera);
+        Ok(())
+    }
+
+    /// Goes back to the default camera (see `camera`)
+    pub fn reset_camera(&mut self) {
+        self.camera = None;
+    }
+
+    /// The color the target is cleared to before drawing, if any
+    pub fn clear_color(&self) -> Option<Color> {
+        self.clear_color
+    }
+
+    pub fn set_clear_color<C: Into<Color>>(&mut self, color: C) {
+        self.clear_color = Some(color.into());
+    }
+
+    /// Keeps the previous contents of the target when drawing into it,
+    /// so that several calls to `render_to_target` can build up an image
+    pub fn retain_previous_contents(&mut self) {
+        self.clear_color = None;
+    }
+
+    /// Returns the RGBA bytes of the target, 4 bytes per pixel,
+    /// row by row starting from the upper-left corner
+    pub fn read_pixels(&self, graphics: &mut Graphics2D) -> Result<Vec<u8>> {
+        graphics.ensure_polling()?;
+        let gpu = graphics
+            .renderer
+            .gpu()
+            .expect("Render targets only exist on the GPU");
+        futures::executor::block_on(read_texture(
+            &gpu.device,
+            &gpu.queue,
+            self.sheet.texture(),
+            self.width(),
+            self.height(),
+        ))
+    }
+
+    pub(super) fn pass_target(&self) -> PassTarget<'_> {
+        PassTarget {
+            view: &self.view,
+            depth_view: &self.depth_view,
+            format: OFFSCREEN_FORMAT,
+            size: (self.width(), self.height()),
+            scale: self.scale,
+            camera: self.camera(),
+            clear_color: self.clear_color,
+        }
+    }
+}
@@ -0,0 +1,384 @@
+use super::*;
+use ab_glyph::Font as _;
+use ab_glyph::ScaleFont as _;
+
+/// Horizontal alignment of each line in a block of text
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextAlign {
+    Left,
+    Center,
+    Right,
+}
+
+/// How free-positioned text should be laid out and drawn
+#[derive(Debug, Clone, Copy)]
+pub struct TextOptions {
+    /// The height of a line of text
+    pub size: f32,
+    pub color: Color,
+    pub align: TextAlign,
+
+    /// If set, lines are wrapped at word boundaries to fit within this width,
+    /// and alignment is relative to this width instead of the widest line
+    pub max_width: Option<f32>,
+}
+
+impl TextOptions {
+    /// White, left aligned, unwrapped text of the given size
+    pub fn new(size: f32) -> Self {
+        Self {
+            size,
+            color: [1.0, 1.0, 1.0].into(),
+            align: TextAlign::Left,
+            max_width: None,
+        }
+    }
+
+    pub fn color<C: Into<Color>>(mut self, color: C) -> Self {
+        self.color = color.into();
+        self
+    }
+
+    pub fn align(mut self, align: TextAlign) -> Self {
+        self.align = align;
+        self
+    }
+
+    pub fn max_width(mut self, max_width: f32) -> Self {
+        self.max_width = Some(max_width);
+        self
+    }
+}
+
+/// Font metrics and glyphs used for free-positioned text
+pub(super) enum TextFace {
+    /// The builtin courier charmap, used if no font was set
+    Courier,
+    Font(GlyphAtlas),
+}
+
+impl TextFace {
+    fn line_height(&self, size: f32) -> f32 {
+        match self {
+            TextFace::Courier => size,
+            TextFace::Font(atlas) => {
+                let scaled = atlas.font().inner().as_scaled(size);
+                scaled.height() + scaled.line_gap()
+            }
+        }
+    }
+
+    fn advance(&self, ch: char, size: f32) -> f32 {
+        match self {
+            TextFace::Courier => size / res::CHAR_HEIGHT_TO_WIDTH_RATIO,
+            TextFace::Font(atlas) => {
+                let font = atlas.font().inner();
+                font.as_scaled(size).h_advance(font.glyph_id(ch))
+            }
+        }
+    }
+
+    fn kern(&self, a: char, b: char, size: f32) -> f32 {
+        match self {
+            TextFace::Courier => 0.0,
+            TextFace::Font(atlas) => {
+                let font = atlas.font().inner();
+                font.as_scaled(size)
+                    .kern(font.glyph_id(a), font.glyph_id(b))
+            }
+        }
+    }
+
+    /// Returns the source index and destination rectangle of the sprite
+    /// for a glyph whose origin is at `x` on the line whose top is at `y`.
+    /// Returns None for whitespace.
+    fn sprite(
+        &mut self,
+        gpu: Option<&Gpu>,
+        ch: char,
+        x: f32,
+        y: f32,
+        size: f32,
+    ) -> Option<(usize, Rect)> {
+        if ch.is_whitespace() {
+            return None;
+        }
+        match self {
+            TextFace::Courier => {
+                // Like in the text grid, courier glyphs only fill the middle
+                // of their cells, so they're drawn at twice the size
+                let src = res::char_to_charmap_index(ch).unwrap_or(res::CHAR_FALLBACK_INDEX);
+                let width = size / res::CHAR_HEIGHT_TO_WIDTH_RATIO;
+                let dst = [
+                    x - width / 2.0,
+                    y - size / 2.0,
+                    x + width * 1.5,
+                    y + size * 1.5,
+                ];
+                Some((src, dst.into()))
+            }
+            TextFace::Font(atlas) => {
+                let src = atlas.index(gpu, ch);
+                let k = size / atlas.px_height();
+                let [cell_width, cell_height] = atlas.cell_size();
+                let left = x - atlas.padding() * k;
+                let dst = [left, y, left + cell_width * k, y + cell_height * k];
+                Some((src, dst.into()))
+            }
+        }
+    }
+
+    fn sheet_grid(&self) -> (usize, usize) {
+        match self {
+            TextFace::Courier => (res::CHARMAP_NROWS, res::CHARMAP_NCOLS),
+            TextFace::Font(atlas) => (atlas.nrows(), atlas.ncols()),
+        }
+    }
+}
+
+/// A single laid out line: characters with their x offsets
+/// from the start of the line, and the width of the line
+struct Line {
+    glyphs: Vec<(char, f32)>,
+    width: f32,
+}
+
+impl TextFace {
+    /// Splits text into lines, wrapping at word boundaries if max_width is set
+    fn layout(&self, text: &str, size: f32, max_width: Option<f32>) -> Vec<Line> {
+        let mut lines = vec![];
+        for paragraph in text.split('\n') {
+            let mut line = Line {
+                glyphs: vec![],
+                width: 0.0,
+            };
+            // Set once the paragraph has been wrapped, so that the new lines
+            // don't start with the whitespace they were wrapped at
+            let mut wrapped = false;
+            for word in split_words(paragraph) {
+                if wrapped && line.glyphs.is_empty() && word.trim().is_empty() {
+                    continue;
+                }
+                let start = line.glyphs.len();
+                let width_before = line.width;
+                self.push_str(&mut line, word, size);
+                let overflows = max_width.map(|w| line.width > w).unwrap_or(false);
+                if overflows && start > 0 && !word.trim().is_empty() {
+                    // Move the word to a new line, dropping the
+                    // whitespace it was wrapped at
+                    line.glyphs.truncate(start);
+                    line.width = width_before;
+                    trim_trailing_whitespace(&mut line, self, size);
+                    lines.push(line);
+                    line = Line {
+                        glyphs: vec![],
+                        width: 0.0,
+                    };
+                    self.push_str(&mut line, word, size);
+                    wrapped = true;
+                }
+                // Words that don't fit on a line of their own are broken
+                // between characters
+                if let Some(max_width) = max_width {
+                    while line.width > max_width && line.glyphs.len() > 1 {
+                        let split = self.fit(&line, max_width, size).max(1);
+                        let rest: String = line.glyphs[split..].iter().map(|g| g.0).collect();
+                        line.glyphs.truncate(split);
+                        line.width = self.line_width(&line, size);
+                        lines.push(line);
+                        line = Line {
+                            glyphs: vec![],
+                            width: 0.0,
+                        };
+                        self.push_str(&mut line, rest.trim_start(), size);
+                        wrapped = true;
+                    }
+                }
+            }
+            lines.push(line);
+        }
+        lines
+    }
+
+    fn push_str(&self, line: &mut Line, s: &str, size: f32) {
+        for ch in s.chars() {
+            let mut x = line.width;
+            if let Some(&(prev, _)) = line.glyphs.last() {
+                x += self.kern(prev, ch, size);
+            }
+            line.glyphs.push((ch, x));
+            line.width = x + self.advance(ch, size);
+        }
+    }
+
+    /// Number of leading glyphs of the line that fit within max_width
+    fn fit(&self, line: &Line, max_width: f32, size: f32) -> usize {
+        line.glyphs
+            .iter()
+            .position(|&(ch, x)| x + self.advance(ch, size) > max_width)
+            .unwrap_or(line.glyphs.len())
+    }
+
+    fn line_width(&self, line: &Line, size: f32) -> f32 {
+        match line.glyphs.last() {
+            Some(&(ch, x)) => x + self.advance(ch, size),
+            None => 0.0,
+        }
+    }
+}
+
+fn trim_trailing_whitespace(line: &mut Line, face: &TextFace, size: f32) {
+    while line
+        .glyphs
+        .last()
+        .map(|(ch, _)| ch.is_whitespace())
+        .unwrap_or(false)
+    {
+        line.glyphs.pop();
+    }
+    line.width = face.line_width(line, size);
+}
+
+/// Splits a line into alternating runs of whitespace and non-whitespace
+fn split_words(s: &str) -> Vec<&str> {
+    let mut words = vec![];
+    let mut start = 0;
+    let mut prev_space = None;
+    for (i, ch) in s.char_indices() {
+        let space = ch.is_whitespace();
+        if prev_space.is_some() && prev_space != Some(space) {
+            words.push(&s[start..i]);
+            start = i;
+        }
+        prev_space = Some(space);
+    }
+    if start < s.len() {
+        words.push(&s[start..]);
+    }
+    words
+}
+
+/// The sprites of all text drawn with `draw_text_at` since
+/// the last `clear_text`
+pub(super) struct FreeText {
+    face: TextFace,
+    sheet: Rc<Sheet>,
+    descs: Vec<SpriteDesc>,
+
+    /// Set when descs has changed and the batch needs to be rebuilt
+    changed: bool,
+}
+
+impl FreeText {
+    pub fn new(face: TextFace, sheet: Rc<Sheet>) -> Self {
+        Self {
+            face,
+            sheet,
+            descs: vec![],
+            changed: true,
+        }
+    }
+
+    pub fn measure(&self, text: &str, options: &TextOptions) -> Dimensions {
+        let lines = self.face.layout(text, options.size, options.max_width);
+        let widest = lines.iter().map(|l| l.width).fold(0.0, f32::max);
+        let height = self.face.line_height(options.size) * lines.len() as f32;
+        [widest, height].into()
+    }
+
+    pub fn draw(&mut self, gpu: Option<&Gpu>, point: Point, text: &str, options: &TextOptions) {
+        let size = options.size;
+        let lines = self.face.layout(text, size, options.max_width);
+        let widest = lines.iter().map(|l| l.width).fold(0.0, f32::max);
+        let block_width = options.max_width.unwrap_or(widest);
+        let line_height = self.face.line_height(size);
+        for (i, line) in lines.iter().enumerate() {
+            let x0 = point.x
+                + match options.align {
+                    TextAlign::Left => 0.0,
+                    TextAlign::Center => (block_width - line.width) / 2.0,
+                    TextAlign::Right => block_width - line.width,
+                };
+            let y = point.y + line_height * i as f32;
+            for &(ch, x) in &line.glyphs {
+                if let Some((src, dst)) = self.face.sprite(gpu, ch, x0 + x, y, size) {
+                    self.descs.push(SpriteDesc {
+                        src_rect: None,
+                        flip: [false, false],
+                        z: BUILTIN_Z,
+                        visible: true,
+                        transform: Affine::IDENTITY,
+                        tiling: None,
+                        src,
+                        dst,
+                        rotate: 0.0,
+                        color: options.color,
+                    });
+                }
+            }
+        }
+        self.changed = true;
+    }
+
+    pub fn clear(&mut self) {
+        self.descs.clear();
+        self.changed = true;
+    }
+
+    /// Updates the given batch if anything changed since the last call.
+    ///
+    /// The batch is reused as long as it draws from this text's sheet,
+    /// so that its instance buffer is only reallocated when it grows
+    pub fn rebuild(&mut self, graphics: &mut Graphics2D, batch: &mut Option<Batch>) {
+        if !self.changed {
+            return;
+        }
+        self.changed = false;
+        match batch {
+            Some(batch) if Rc::ptr_eq(batch.sheet(), &self.sheet) => {
+                batch.clear();
+                for desc in &self.descs {
+                    batch.push(desc);
+                }
+            }
+            _ => {
+                let (nrows, ncols) = self.face.sheet_grid();
+                *batch = Some(Batch::new(
+                    graphics,
+                    self.sheet.clone(),
+                    nrows,
+                    ncols,
+                    &self.descs,
+                ));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Lays out text with every courier glyph 1 unit wide
+    fn layout(text: &str, max_width: f32) -> Vec<String> {
+        let size = res::CHAR_HEIGHT_TO_WIDTH_RATIO;
+        TextFace::Courier
+            .layout(text, size, Some(max_width))
+            .iter()
+            .map(|line| line.glyphs.iter().map(|g| g.0).collect())
+            .collect()
+    }
+
+    #[test]
+    fn wrapped_lines_have_no_leading_whitespace() {
+        assert_eq!(layout("aaa bbb", 5.0), vec!["aaa", "bbb"]);
+        assert_eq!(layout("aaaa   bbbb", 4.0), vec!["aaaa", "bbbb"]);
+        assert_eq!(layout("aaaaaa bb", 4.0), vec!["aaaa", "aa", "bb"]);
+        assert_eq!(layout("aaaaa  bb", 4.0), vec!["aaaa", "a", "bb"]);
+    }
+
+    #[test]
+    fn leading_whitespace_of_a_paragraph_is_kept() {
+        assert_eq!(layout("  aa bb\n cc", 5.0), vec!["  aa", "bb", " cc"]);
+    }
+}
@@ -0,0 +1,185 @@
+use crate::Point;
+use std::ops;
+
+/// A 2D affine transformation (2x3 matrix)
+///
+/// Maps a point [x, y] to
+/// [a * x + c * y + e, b * x + d * y + f]
+/// where [a, b, c, d, e, f] are the entries given to `new`
+/// (the same order as CSS's `matrix(..)`)
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Affine {
+    m: [f32; 6],
+}
+
+impl Affine {
+    pub const IDENTITY: Affine = Affine {
+        m: [1.0, 0.0, 0.0, 1.0, 0.0, 0.0],
+    };
+
+    pub const fn new(a: f32, b: f32, c: f32, d: f32, e: f32, f: f32) -> Self {
+        Self {
+            m: [a, b, c, d, e, f],
+        }
+    }
+
+    pub fn translation(dx: f32, dy: f32) -> Self {
+        Self::new(1.0, 0.0, 0.0, 1.0, dx, dy)
+    }
+
+    pub fn scaling(sx: f32, sy: f32) -> Self {
+        Self::new(sx, 0.0, 0.0, sy, 0.0, 0.0)
+    }
+
+    /// Clockwise rotation in radians around the origin
+    /// (the same direction as `SpriteDesc::rotate`)
+    pub fn rotation(theta: f32) -> Self {
+        let (sin, cos) = theta.sin_cos();
+        Self::new(cos, sin, -sin, cos, 0.0, 0.0)
+    }
+
+    /// Shears by the given angles in radians.
+    /// `x_angle` slants vertical lines, `y_angle` slants horizontal lines
+    pub fn skew(x_angle: f32, y_angle: f32) -> Self {
+        Self::new(1.0, y_angle.tan(), x_angle.tan(), 1.0, 0.0, 0.0)
+    }
+
+    /// The transformation that applies `self` and then `other`
+    pub fn then(self, other: Affine) -> Self {
+        let [a1, b1, c1, d1, e1, f1] = self.m;
+        let [a2, b2, c2, d2, e2, f2] = other.m;
+        Self::new(
+            a2 * a1 + c2 * b1,
+            b2 * a1 + d2 * b1,
+            a2 * c1 + c2 * d1,
+            b2 * c1 + d2 * d1,
+            a2 * e1 + c2 * f1 + e2,
+            b2 * e1 + d2 * f1 + f2,
+        )
+    }
+
+    /// The same transformation, but with `pivot` as the fixed point
+    /// instead of the origin (e.g. to rotate around a given point)
+    pub fn around<P: Into<Point>>(self, pivot: P) -> Self {
+        let pivot = pivot.into();
+        Self::translation(-pivot.x, -pivot.y)
+            .then(self)
+            .then(Self::translation(pivot.x, pivot.y))
+    }
+
+    /// Returns the inverse transformation, or None if the
+    /// transformation collapses everything onto a line or point
+    pub fn inverse(&self) -> Option<Self> {
+        let [a, b, c, d, e, f] = self.m;
+        let det = a * d - b * c;
+        if det == 0.0 || !det.is_finite() {
+            return None;
+        }
+        let (ia, ib, ic, id) = (d / det, -b / det, -c / det, a / det);
+        Some(Self::new(
+            ia,
+            ib,
+            ic,
+            id,
+            -(ia * e + ic * f),
+            -(ib * e + id * f),
+        ))
+    }
+
+    pub fn apply<P: Into<Point>>(&self, point: P) -> Point {
+        let Point { x, y } = point.into();
+        let [a, b, c, d, e, f] = self.m;
+        Point {
+            x: a * x + c * y + e,
+            y: b * x + d * y + f,
+        }
+    }
+
+    /// The entries [a, b, c, d, e, f]
+    pub fn to_array(&self) -> [f32; 6] {
+        self.m
+    }
+}
+
+impl Default for Affine {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+/// `x * y` applies `y` first and then `x`, like matrix multiplication
+impl ops::Mul for Affine {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        other.then(self)
+    }
+}
+
+impl From<[f32; 6]> for Affine {
+    fn from(m: [f32; 6]) -> Self {
+        Self { m }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn det(t: Affine) -> f32 {
+        let [a, b, c, d, _, _] = t.to_array();
+        a * d - b * c
+    }
+
+    fn assert_close(p: Point, expected: [f32; 2]) {
+        assert!(
+            (p.x - expected[0]).abs() < 1e-5 && (p.y - expected[1]).abs() < 1e-5,
+            "{:?} != {:?}",
+            p,
+            expected
+        );
+    }
+
+    #[test]
+    fn then_applies_self_first() {
+        let scale = Affine::scaling(2.0, 3.0);
+        let translate = Affine::translation(1.0, -1.0);
+        assert_close(scale.then(translate).apply([1.0, 1.0]), [3.0, 2.0]);
+        assert_close(translate.then(scale).apply([1.0, 1.0]), [4.0, 0.0]);
+        assert_eq!(translate * scale, scale.then(translate));
+    }
+
+    #[test]
+    fn rotation_is_clockwise() {
+        // y points down, so clockwise takes +x to +y
+        let quarter = Affine::rotation(std::f32::consts::FRAC_PI_2);
+        assert_close(quarter.apply([1.0, 0.0]), [0.0, 1.0]);
+        assert_close(quarter.around([1.0, 1.0]).apply([2.0, 1.0]), [1.0, 2.0]);
+    }
+
+    #[test]
+    fn inverse_undoes_the_transformation() {
+        let t = Affine::rotation(0.3)
+            .then(Affine::scaling(2.0, -0.5))
+            .then(Affine::skew(0.2, 0.1))
+            .then(Affine::translation(5.0, 7.0));
+        let inverse = t.inverse().unwrap();
+        for &p in &[[0.0, 0.0], [1.0, 2.0], [-3.0, 4.5]] {
+            assert_close(inverse.apply(t.apply(p)), p);
+            assert_close(t.apply(inverse.apply(p)), p);
+        }
+        assert_eq!(Affine::scaling(1.0, 0.0).inverse(), None);
+        assert_eq!(Affine::scaling(f32::INFINITY, 1.0).inverse(), None);
+    }
+
+    #[test]
+    fn determinant_is_negative_for_mirroring_transformations() {
+        assert!(det(Affine::IDENTITY) > 0.0);
+        assert!(det(Affine::rotation(2.0)) > 0.0);
+        assert!(det(Affine::skew(0.3, -0.2).then(Affine::translation(4.0, 4.0))) > 0.0);
+        assert!(det(Affine::scaling(-1.0, 1.0)) < 0.0);
+        assert!(det(Affine::scaling(1.0, -2.0).then(Affine::rotation(1.0))) < 0.0);
+        assert!(det(Affine::scaling(-1.0, -1.0)) > 0.0);
+    }
+}
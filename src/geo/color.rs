@@ -12,14 +12,7 @@ impl Color {
 
     pub fn to_u8_array(&self) -> [u8; 4] {
         fn translate(x: f32) -> u8 {
-            let x = if x < 0.0 {
-                0.0
-            } else if x > 1.0 {
-                1.0
-            } else {
-                x
-            };
-            (x * 255.0) as u8
+            (x.clamp(0.0, 1.0) * 255.0) as u8
         }
         let (r, g, b, a) = self.unpack();
         [translate(r), translate(g), translate(b), translate(a)]
@@ -1,7 +1,9 @@
+mod affine;
 mod color;
 mod dim;
 mod point;
 mod rect;
+pub use affine::*;
 pub use color::*;
 pub use dim::*;
 pub use point::*;
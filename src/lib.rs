@@ -9,8 +9,10 @@ mod g2d;
 mod geo;
 mod res;
 mod shaders;
+mod term;
 
 pub use error::*;
 pub use g2d::*;
 pub use geo::*;
 pub use shaders::*;
+pub use term::*;
@@ -9,9 +9,12 @@ pub(crate) const CHARMAP_NCOLS: usize = 32;
 pub(crate) const CHAR_HEIGHT_TO_WIDTH_RATIO: f32 = 1.5;
 pub(crate) const CHAR_EMPTY_SPACE_INDEX: usize = CHARMAP_NROWS * CHARMAP_NCOLS - 1;
 
+/// Index of '?', used for characters that are not in the charmap
+pub(crate) const CHAR_FALLBACK_INDEX: usize = '?' as usize - '!' as usize;
+
 pub(crate) fn char_to_charmap_index(c: char) -> Option<usize> {
     match c {
-        _ if c >= '!' && c <= '~' => Some(c as usize - '!' as usize),
+        _ if ('!'..='~').contains(&c) => Some(c as usize - '!' as usize),
         ' ' => Some(CHAR_EMPTY_SPACE_INDEX),
         _ => None,
     }
@@ -0,0 +1,28 @@
+// bloom.frag
+#version 450
+
+// Adds the blurred bright parts of the image back onto it
+
+layout(location=0) in vec2 v_tex_coords;
+layout(location=0) out vec4 f_color;
+
+// The blurred bright parts
+layout(set = 0, binding = 0) uniform texture2D t_input;
+layout(set = 0, binding = 1) uniform sampler s_input;
+
+layout(set = 1, binding = 0) uniform PostUniform {
+    vec2 u_texel_size;
+    vec2 u_direction;
+    // x: intensity
+    vec4 u_params;
+};
+
+// The image before bloom
+layout(set = 2, binding = 0) uniform texture2D t_scene;
+layout(set = 2, binding = 1) uniform sampler s_scene;
+
+void main() {
+    vec4 scene = texture(sampler2D(t_scene, s_scene), v_tex_coords);
+    vec4 bloom = texture(sampler2D(t_input, s_input), v_tex_coords);
+    f_color = vec4(scene.rgb + bloom.rgb * u_params.x, scene.a);
+}
@@ -0,0 +1,31 @@
+// blur.frag
+#version 450
+
+// One direction of a separable gaussian blur
+
+layout(location=0) in vec2 v_tex_coords;
+layout(location=0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform texture2D t_input;
+layout(set = 0, binding = 1) uniform sampler s_input;
+
+layout(set = 1, binding = 0) uniform PostUniform {
+    vec2 u_texel_size;
+    vec2 u_direction;
+    // x: radius in pixels
+    vec4 u_params;
+};
+
+// Gaussian with a standard deviation of 2 taps, normalized
+const float WEIGHTS[5] = float[](0.2042, 0.1802, 0.1238, 0.0663, 0.0276);
+
+void main() {
+    vec2 tap = u_direction * u_texel_size * max(u_params.x, 0.0) / 4.0;
+    vec4 color = texture(sampler2D(t_input, s_input), v_tex_coords) * WEIGHTS[0];
+    for (int i = 1; i < 5; i++) {
+        vec2 offset = tap * float(i);
+        color += texture(sampler2D(t_input, s_input), v_tex_coords + offset) * WEIGHTS[i];
+        color += texture(sampler2D(t_input, s_input), v_tex_coords - offset) * WEIGHTS[i];
+    }
+    f_color = color;
+}
@@ -0,0 +1,25 @@
+// bright.frag
+#version 450
+
+// Keeps only the parts of the image brighter than a threshold,
+// as the first step of bloom
+
+layout(location=0) in vec2 v_tex_coords;
+layout(location=0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform texture2D t_input;
+layout(set = 0, binding = 1) uniform sampler s_input;
+
+layout(set = 1, binding = 0) uniform PostUniform {
+    vec2 u_texel_size;
+    vec2 u_direction;
+    // x: luminance threshold
+    vec4 u_params;
+};
+
+void main() {
+    vec4 color = texture(sampler2D(t_input, s_input), v_tex_coords);
+    float luminance = dot(color.rgb, vec3(0.2126, 0.7152, 0.0722));
+    float excess = max(luminance - u_params.x, 0.0);
+    f_color = vec4(color.rgb * (excess / max(luminance, 0.0001)), 1.0);
+}
@@ -0,0 +1,44 @@
+// crt.frag
+#version 450
+
+// An old CRT monitor look: curved glass, scanlines and
+// an aperture grille of red, green and blue stripes
+
+layout(location=0) in vec2 v_tex_coords;
+layout(location=0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform texture2D t_input;
+layout(set = 0, binding = 1) uniform sampler s_input;
+
+layout(set = 1, binding = 0) uniform PostUniform {
+    vec2 u_texel_size;
+    vec2 u_direction;
+    // x: scanline intensity, y: curvature, z: grille intensity,
+    // w: scanline period in pixels
+    vec4 u_params;
+};
+
+const float PI = 3.14159265;
+
+void main() {
+    // Barrel distortion around the center
+    vec2 centered = v_tex_coords * 2.0 - 1.0;
+    centered *= 1.0 + u_params.y * dot(centered, centered);
+    vec2 uv = centered * 0.5 + 0.5;
+    if (uv.x < 0.0 || uv.x > 1.0 || uv.y < 0.0 || uv.y > 1.0) {
+        f_color = vec4(0.0, 0.0, 0.0, 1.0);
+        return;
+    }
+    vec4 color = texture(sampler2D(t_input, s_input), uv);
+
+    float period = max(u_params.w, 1.0);
+    float row = uv.y / u_texel_size.y;
+    float scanline = 0.5 + 0.5 * cos(row / period * 2.0 * PI);
+    color.rgb *= 1.0 - u_params.x * (1.0 - scanline);
+
+    int column = int(v_tex_coords.x / u_texel_size.x) % 3;
+    vec3 grille = vec3(column == 0, column == 1, column == 2);
+    color.rgb *= mix(vec3(1.0), grille * 1.5 + 0.25, u_params.z);
+
+    f_color = color;
+}
@@ -0,0 +1,60 @@
+use crate::Result;
+use naga::back::spv;
+use naga::front::glsl;
+use naga::valid::Capabilities;
+use naga::valid::ValidationFlags;
+use naga::valid::Validator;
+
+/// The kind of GLSL shader to compile
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShaderStage {
+    Vertex,
+    Fragment,
+}
+
+/// Compiles (Vulkan flavored) GLSL source to SPIR-V bytes
+///
+/// Only available with the `glsl` cargo feature. Compilation is done
+/// in pure Rust, so no native shader compiler is needed
+pub fn compile_glsl(source: &str, stage: ShaderStage) -> Result<Vec<u8>> {
+    let stage = match stage {
+        ShaderStage::Vertex => naga::ShaderStage::Vertex,
+        ShaderStage::Fragment => naga::ShaderStage::Fragment,
+    };
+    let module = match glsl::Frontend::default().parse(&glsl::Options::from(stage), source) {
+        Ok(module) => module,
+        Err(errors) => {
+            let messages: Vec<_> = errors
+                .iter()
+                .map(|e| {
+                    let location = e.meta.location(source);
+                    format!("{}:{}: {}", location.line_number, location.line_position, e)
+                })
+                .collect();
+            err!("Failed to parse GLSL shader:\n{}", messages.join("\n"));
+        }
+    };
+    let info = match Validator::new(ValidationFlags::all(), Capabilities::empty()).validate(&module)
+    {
+        Ok(info) => info,
+        Err(e) => {
+            err!("Invalid GLSL shader:\n{}", e.emit_to_string(source));
+        }
+    };
+    // The flags are spelled out so that the output doesn't depend on
+    // whether a2d is built with debug assertions
+    let options = spv::Options {
+        flags: spv::WriterFlags::empty(),
+        ..spv::Options::default()
+    };
+    let words = match spv::write_vec(&module, &info, &options, None) {
+        Ok(words) => words,
+        Err(e) => {
+            err!("Failed to write SPIR-V: {}", e);
+        }
+    };
+    Ok(words
+        .iter()
+        .flat_map(|word| word.to_le_bytes().to_vec())
+        .collect())
+}
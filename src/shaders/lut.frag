@@ -0,0 +1,52 @@
+// lut.frag
+#version 450
+
+// Color grading through a lookup table.
+//
+// The table is an N*N x N image made of N slices of N x N pixels side by
+// side: blue selects the slice, red the column and green the row.
+// Colors index the table in sRGB space, as image editors produce them
+
+layout(location=0) in vec2 v_tex_coords;
+layout(location=0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform texture2D t_input;
+layout(set = 0, binding = 1) uniform sampler s_input;
+
+layout(set = 1, binding = 0) uniform PostUniform {
+    vec2 u_texel_size;
+    vec2 u_direction;
+    // x: size of the table (N), y: strength
+    vec4 u_params;
+};
+
+layout(set = 2, binding = 0) uniform texture2D t_lut;
+layout(set = 2, binding = 1) uniform sampler s_lut;
+
+vec3 linear_to_srgb(vec3 c) {
+    vec3 low = c * 12.92;
+    vec3 high = 1.055 * pow(c, vec3(1.0 / 2.4)) - 0.055;
+    return mix(high, low, vec3(lessThanEqual(c, vec3(0.0031308))));
+}
+
+vec3 lookup(vec3 srgb, float slice) {
+    float size = u_params.x;
+    vec2 uv = vec2(
+        (slice * size + srgb.r * (size - 1.0) + 0.5) / (size * size),
+        (srgb.g * (size - 1.0) + 0.5) / size
+    );
+    return texture(sampler2D(t_lut, s_lut), uv).rgb;
+}
+
+void main() {
+    vec4 color = texture(sampler2D(t_input, s_input), v_tex_coords);
+    vec3 srgb = linear_to_srgb(clamp(color.rgb, 0.0, 1.0));
+    float blue = srgb.b * (u_params.x - 1.0);
+    float slice = floor(blue);
+    vec3 graded = mix(
+        lookup(srgb, slice),
+        lookup(srgb, min(slice + 1.0, u_params.x - 1.0)),
+        blue - slice
+    );
+    f_color = vec4(mix(color.rgb, graded, u_params.y), color.a);
+}
@@ -1,22 +1,101 @@
-#[cfg(target_os = "windows")]
-macro_rules! sep {
-    () => {
-        "\\"
-    };
-}
+//! The builtin sprite and post-processing shaders
+//!
+//! The GLSL sources in this directory are compiled to SPIR-V ahead of
+//! time and the SPIR-V is checked in, so that building a2d doesn't need
+//! a native shader compiler.
+//!
+//! After editing a source, regenerate the SPIR-V with
+//! `cargo run --example compile_shaders --features glsl`.
+//! `cargo test --features glsl` (run by CI) checks that the SPIR-V is
+//! up to date.
 
-#[cfg(not(target_os = "windows"))]
-macro_rules! sep {
-    () => {
-        "/"
-    };
-}
+#[cfg(feature = "glsl")]
+mod glsl;
 
-macro_rules! get_bytes {
-    ($filename:tt) => {
-        include_bytes!(concat!(env!("OUT_DIR"), sep!(), $filename))
-    };
-}
+#[cfg(feature = "glsl")]
+pub use glsl::*;
+
+pub const VERT: &[u8] = include_bytes!("shader.vert.spv");
+pub const FRAG: &[u8] = include_bytes!("shader.frag.spv");
 
-pub const VERT: &[u8] = get_bytes!("shader.vert.spirv");
-pub const FRAG: &[u8] = get_bytes!("shader.frag.spirv");
+/// Full-screen triangle shared by all post-processing passes
+pub const POST_VERT: &[u8] = include_bytes!("post.vert.spv");
+pub const BLUR_FRAG: &[u8] = include_bytes!("blur.frag.spv");
+pub const BRIGHT_FRAG: &[u8] = include_bytes!("bright.frag.spv");
+pub const BLOOM_FRAG: &[u8] = include_bytes!("bloom.frag.spv");
+pub const VIGNETTE_FRAG: &[u8] = include_bytes!("vignette.frag.spv");
+pub const LUT_FRAG: &[u8] = include_bytes!("lut.frag.spv");
+pub const CRT_FRAG: &[u8] = include_bytes!("crt.frag.spv");
+
+#[cfg(all(test, feature = "glsl"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spirv_matches_glsl_sources() {
+        let shaders = [
+            (
+                "shader.vert",
+                include_str!("shader.vert"),
+                ShaderStage::Vertex,
+                VERT,
+            ),
+            (
+                "shader.frag",
+                include_str!("shader.frag"),
+                ShaderStage::Fragment,
+                FRAG,
+            ),
+            (
+                "post.vert",
+                include_str!("post.vert"),
+                ShaderStage::Vertex,
+                POST_VERT,
+            ),
+            (
+                "blur.frag",
+                include_str!("blur.frag"),
+                ShaderStage::Fragment,
+                BLUR_FRAG,
+            ),
+            (
+                "bright.frag",
+                include_str!("bright.frag"),
+                ShaderStage::Fragment,
+                BRIGHT_FRAG,
+            ),
+            (
+                "bloom.frag",
+                include_str!("bloom.frag"),
+                ShaderStage::Fragment,
+                BLOOM_FRAG,
+            ),
+            (
+                "vignette.frag",
+                include_str!("vignette.frag"),
+                ShaderStage::Fragment,
+                VIGNETTE_FRAG,
+            ),
+            (
+                "lut.frag",
+                include_str!("lut.frag"),
+                ShaderStage::Fragment,
+                LUT_FRAG,
+            ),
+            (
+                "crt.frag",
+                include_str!("crt.frag"),
+                ShaderStage::Fragment,
+                CRT_FRAG,
+            ),
+        ];
+        for &(name, source, stage, spirv) in &shaders {
+            let compiled = compile_glsl(source, stage).unwrap();
+            assert!(
+                compiled == spirv,
+                "{}.spv is out of date, see src/shaders/mod.rs",
+                name
+            );
+        }
+    }
+}
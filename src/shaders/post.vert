@@ -0,0 +1,17 @@
+// post.vert
+#version 450
+
+// Draws a single triangle covering the whole target, so that
+// post-processing passes don't need a vertex buffer
+
+layout(location=0) out vec2 v_tex_coords;
+
+void main() {
+    vec2 position = vec2(
+        float((gl_VertexIndex & 1) * 4 - 1),
+        float((gl_VertexIndex & 2) * 2 - 1)
+    );
+    // wgpu's texture coordinates have y pointing down
+    v_tex_coords = vec2(position.x + 1.0, 1.0 - position.y) * 0.5;
+    gl_Position = vec4(position, 0.0, 1.0);
+}
@@ -3,11 +3,35 @@
 
 layout(location=0) in vec2 v_tex_coords;
 layout(location=1) in vec4 v_color_factor;
+layout(location=2) flat in vec2 v_src_ul;
+layout(location=3) flat in vec2 v_src_size;
+layout(location=4) in vec2 v_tile_coords;
+layout(location=5) flat in float v_tiled;
 layout(location=0) out vec4 f_color;
 
 layout(set = 0, binding = 0) uniform texture2D t_diffuse;
 layout(set = 0, binding = 1) uniform sampler s_diffuse;
 
 void main() {
-    f_color = texture(sampler2D(t_diffuse, s_diffuse), v_tex_coords) * v_color_factor;
+    if (v_tiled != 0.0) {
+        // Gradients are taken from the unwrapped coordinates, so the
+        // jump at the tile seams doesn't select a tiny mip level
+        vec2 unwrapped = v_src_ul + v_tile_coords * v_src_size;
+        vec2 tex_coords = v_src_ul + fract(v_tile_coords) * v_src_size;
+        f_color = textureGrad(
+            sampler2D(t_diffuse, s_diffuse),
+            tex_coords,
+            dFdx(unwrapped),
+            dFdy(unwrapped)
+        ) * v_color_factor;
+    } else {
+        f_color = texture(sampler2D(t_diffuse, s_diffuse), v_tex_coords) * v_color_factor;
+    }
+
+    // Fully transparent fragments must not write depth, or they would
+    // hide sprites with a lower z that are drawn later. Partially
+    // transparent fragments still do (see `SpriteDesc::z`)
+    if (f_color.a <= 0.0) {
+        discard;
+    }
 }
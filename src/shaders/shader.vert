@@ -7,17 +7,34 @@ layout(location=2) in vec2 dst_ul;
 layout(location=3) in vec2 dst_lr;
 layout(location=4) in float rotate_theta;
 layout(location=5) in vec4 color_factor;
+layout(location=6) in float z;
+layout(location=7) in float visible;
+layout(location=8) in vec3 transform_x;
+layout(location=9) in vec3 transform_y;
+layout(location=10) in vec2 tile_repeat;
+layout(location=11) in vec2 tile_offset;
 
 layout(location=0) out vec2 v_tex_coords;
 layout(location=1) out vec4 v_color_factor;
+layout(location=2) flat out vec2 v_src_ul;
+layout(location=3) flat out vec2 v_src_size;
+layout(location=4) out vec2 v_tile_coords;
+layout(location=5) flat out float v_tiled;
 
 layout(set = 1, binding = 0) uniform Uniform {
     vec2 u_scale;
+    vec2 u_camera_position;
+    vec2 u_viewport_center;
+    float u_camera_zoom;
+    float u_camera_rotate;
 };
 
-layout(set = 2, binding = 0) uniform TranslationUniform {
+layout(set = 2, binding = 0) uniform BatchUniform {
     vec2 u_per_batch_scale;
     vec2 u_per_batch_translate;
+    vec2 u_per_batch_pivot;
+    float u_per_batch_rotate;
+    vec4 u_per_batch_tint;
 };
 
 const vec2 positions[4] = vec2[4](
@@ -66,7 +83,7 @@ const mat3 normalized_basis = mat3(
 void main() {
     // Just pass color_factor to fragment shader; there isn't any
     // processing to be done for it in the vertex shader
-    v_color_factor = color_factor;
+    v_color_factor = color_factor * u_per_batch_tint;
 
     // ---------------
     // Define some useful matrices for the
@@ -110,15 +127,51 @@ void main() {
     vec3 src_pos3 = normalized_to_src * normalized_pos3;
     vec3 dst_pos3 = normalized_to_dst * normalized_pos3;
     vec3 rot_pos3 = rotate_around_dst_center * dst_pos3;
+
+    // Per-sprite affine transformation relative to the upper-left
+    // corner of the destination rectangle
+    vec3 local_pos3 = vec3(vec2(rot_pos3) - dst_ul, 1.0);
+    rot_pos3 = vec3(
+        dot(transform_x, local_pos3) + dst_ul[0],
+        dot(transform_y, local_pos3) + dst_ul[1],
+        1.0
+    );
+    vec3 batch_pos3 = vec3(
+        (vec2(rot_pos3) - u_per_batch_pivot) * u_per_batch_scale,
+        1.0
+    );
+    vec3 world_pos3 = vec3(
+        vec2(rotation_matrix_around_origin(u_per_batch_rotate) * batch_pos3)
+            + u_per_batch_pivot + u_per_batch_translate - u_camera_position,
+        1.0
+    );
+
+    // The camera's position ends up at the center of the viewport,
+    // and the world is rotated opposite to the camera
     vec3 translated_pos3 = vec3(
-        vec2(rot_pos3) * u_per_batch_scale + u_per_batch_translate,
+        u_viewport_center
+            + u_camera_zoom * vec2(rotation_matrix_around_origin(-u_camera_rotate) * world_pos3),
         1.0
     );
 
     v_tex_coords = vec2(src_pos3);
+
+    // Tiled sprites wrap their texture coordinates in the fragment shader
+    v_src_ul = src_ul;
+    v_src_size = src_lr - src_ul;
+    v_tile_coords = normalized_pos2 * tile_repeat + tile_offset;
+    v_tiled = (tile_repeat == vec2(0.0, 0.0)) ? 0.0 : 1.0;
+
+    // Hidden sprites collapse to a single point so nothing is rasterized
+    if (visible == 0.0) {
+        gl_Position = vec4(0.0, 0.0, 0.0, 1.0);
+        return;
+    }
+
+    // Higher z is closer to the viewer, i.e. a smaller depth value
     gl_Position = vec4(
         vec2(to_wgpu * (translated_pos3 / vec3(u_scale, 1.0))),
-        0.0,
+        0.5 - 0.5 * z,
         1.0
     );
 }
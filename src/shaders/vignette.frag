@@ -0,0 +1,24 @@
+// vignette.frag
+#version 450
+
+// Darkens the image towards its edges
+
+layout(location=0) in vec2 v_tex_coords;
+layout(location=0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform texture2D t_input;
+layout(set = 0, binding = 1) uniform sampler s_input;
+
+layout(set = 1, binding = 0) uniform PostUniform {
+    vec2 u_texel_size;
+    vec2 u_direction;
+    // x: strength, y: radius where darkening starts, z: softness
+    vec4 u_params;
+};
+
+void main() {
+    vec4 color = texture(sampler2D(t_input, s_input), v_tex_coords);
+    float distance = length(v_tex_coords - vec2(0.5)) * 2.0;
+    float shade = smoothstep(u_params.y, u_params.y + max(u_params.z, 0.0001), distance);
+    f_color = vec4(color.rgb * (1.0 - shade * u_params.x), color.a);
+}
@@ -0,0 +1,735 @@
+//! Terminal emulation on top of the builtin text grid
+//!
+//! `Terminal` consumes a byte stream containing text and ANSI/VT100
+//! escape sequences, keeps track of what each cell of the grid should
+//! show, and draws the cells that changed with `Graphics2D::draw_char_styled`.
+use crate::Color;
+use crate::Graphics2D;
+use crate::Result;
+use crate::TextGridDim;
+
+mod palette;
+mod parser;
+
+use parser::*;
+
+const TAB_WIDTH: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Style {
+    fg: Option<Color>,
+    bg: Option<Color>,
+    bold: bool,
+    inverse: bool,
+
+    /// The 16 color palette index of fg, if it was set that way.
+    /// Needed to brighten the color when bold is set
+    fg_index: Option<u8>,
+}
+
+impl Style {
+    const DEFAULT: Style = Style {
+        fg: None,
+        bg: None,
+        bold: false,
+        inverse: false,
+        fg_index: None,
+    };
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Cell {
+    ch: char,
+    style: Style,
+}
+
+impl Cell {
+    const EMPTY: Cell = Cell {
+        ch: ' ',
+        style: Style::DEFAULT,
+    };
+}
+
+/// A terminal emulator that draws onto the text grid of a Graphics2D
+pub struct Terminal {
+    nrows: usize,
+    ncols: usize,
+    cells: Vec<Cell>,
+    dirty: Vec<bool>,
+    row: usize,
+    col: usize,
+    saved_cursor: (usize, usize),
+
+    /// Set after printing into the last column; the next printed
+    /// character goes to the start of the following line
+    pending_wrap: bool,
+
+    style: Style,
+
+    /// First and last row (inclusive) of the scrolling region
+    scroll_top: usize,
+    scroll_bottom: usize,
+
+    default_fg: Color,
+    default_bg: Color,
+    parser: Parser,
+}
+
+impl Terminal {
+    /// Creates a terminal covering a text grid of the given dimensions,
+    /// usually the value returned by `Graphics2D::init_text_grid`
+    pub fn new(dim: TextGridDim) -> Self {
+        let TextGridDim { nrows, ncols } = dim;
+        Self {
+            nrows,
+            ncols,
+            cells: vec![Cell::EMPTY; nrows * ncols],
+            dirty: vec![true; nrows * ncols],
+            row: 0,
+            col: 0,
+            saved_cursor: (0, 0),
+            pending_wrap: false,
+            style: Style::DEFAULT,
+            scroll_top: 0,
+            scroll_bottom: nrows.saturating_sub(1),
+            default_fg: [1.0, 1.0, 1.0].into(),
+            default_bg: [0.0, 0.0, 0.0, 0.0].into(),
+            parser: Parser::new(),
+        }
+    }
+
+    pub fn dim(&self) -> TextGridDim {
+        TextGridDim {
+            nrows: self.nrows,
+            ncols: self.ncols,
+        }
+    }
+
+    /// The current [row, col] of the cursor
+    pub fn cursor(&self) -> [usize; 2] {
+        [self.row, self.col]
+    }
+
+    /// The character currently shown in the given cell,
+    /// or None if the cell is out of bounds
+    pub fn get_char(&self, row: usize, col: usize) -> Option<char> {
+        if row < self.nrows && col < self.ncols {
+            Some(self.cells[row * self.ncols + col].ch)
+        } else {
+            None
+        }
+    }
+
+    /// Sets the colors used when no SGR color is in effect.
+    /// Defaults to white on a transparent background
+    pub fn set_default_colors<C1: Into<Color>, C2: Into<Color>>(&mut self, fg: C1, bg: C2) {
+        self.default_fg = fg.into();
+        self.default_bg = bg.into();
+        self.mark_all_dirty();
+    }
+
+    /// Processes the given bytes, e.g. the output of a subprocess.
+    /// Escape sequences and UTF-8 characters may be split across calls
+    pub fn feed(&mut self, bytes: &[u8]) {
+        let mut actions = vec![];
+        for &byte in bytes {
+            self.parser.advance(byte, &mut actions);
+        }
+        for action in actions {
+            self.perform(action);
+        }
+    }
+
+    /// Draws every cell that changed since the last call onto the
+    /// text grid of the given Graphics2D.
+    /// `Graphics2D::flush` still needs to be called afterwards
+    pub fn draw(&mut self, graphics: &mut Graphics2D) -> Result<()> {
+        for i in 0..self.cells.len() {
+            if !self.dirty[i] {
+                continue;
+            }
+            self.dirty[i] = false;
+            let cell = self.cells[i];
+            let (fg, bg) = self.resolve_colors(cell.style);
+            graphics.draw_char_styled(i / self.ncols, i % self.ncols, cell.ch, fg, bg)?;
+        }
+        Ok(())
+    }
+
+    fn resolve_colors(&self, style: Style) -> (Color, Color) {
+        let fg = match (style.fg_index, style.bold) {
+            (Some(i), true) if i < 8 => palette::indexed(i + 8),
+            _ => style.fg.unwrap_or(self.default_fg),
+        };
+        let bg = style.bg.unwrap_or(self.default_bg);
+        if style.inverse {
+            // an inverted transparent background would make the text
+            // invisible, so fall back to black text in that case
+            let fg_of_inverse = if style.bg.is_none() {
+                [0.0, 0.0, 0.0].into()
+            } else {
+                bg
+            };
+            (fg_of_inverse, fg)
+        } else {
+            (fg, bg)
+        }
+    }
+
+    fn perform(&mut self, action: Action) {
+        // An empty grid has no cells for anything to act on
+        if self.cells.is_empty() {
+            return;
+        }
+        match action {
+            Action::Print(ch) => self.print(ch),
+            Action::Control(byte) => self.control(byte),
+            Action::Esc(byte) => self.esc(byte),
+            Action::Csi {
+                params,
+                private,
+                action,
+            } => {
+                if !private {
+                    self.csi(&params, action);
+                }
+            }
+        }
+    }
+
+    fn print(&mut self, ch: char) {
+        if self.pending_wrap {
+            self.pending_wrap = false;
+            self.col = 0;
+            self.line_feed();
+        }
+        let (row, col) = (self.row, self.col);
+        self.set_cell(
+            row,
+            col,
+            Cell {
+                ch,
+                style: self.style,
+            },
+        );
+        if self.col + 1 < self.ncols {
+            self.col += 1;
+        } else {
+            self.pending_wrap = true;
+        }
+    }
+
+    fn control(&mut self, byte: u8) {
+        match byte {
+            // backspace
+            0x08 => {
+                self.pending_wrap = false;
+                self.col = self.col.saturating_sub(1);
+            }
+            b'\t' => {
+                let next = (self.col / TAB_WIDTH + 1) * TAB_WIDTH;
+                self.col = next.min(self.ncols.saturating_sub(1));
+            }
+            // line feed, vertical tab and form feed
+            b'\n' | 0x0b | 0x0c => self.line_feed(),
+            b'\r' => {
+                self.pending_wrap = false;
+                self.col = 0;
+            }
+            // bell and anything else is ignored
+            _ => {}
+        }
+    }
+
+    fn esc(&mut self, byte: u8) {
+        match byte {
+            b'7' => self.saved_cursor = (self.row, self.col),
+            b'8' => self.restore_cursor(),
+            // index
+            b'D' => self.line_feed(),
+            // next line
+            b'E' => {
+                self.col = 0;
+                self.line_feed();
+            }
+            // reverse index
+            b'M' => {
+                if self.row == self.scroll_top {
+                    self.scroll_down(1);
+                } else {
+                    self.row = self.row.saturating_sub(1);
+                }
+            }
+            // full reset
+            b'c' => {
+                let mut reset = Terminal::new(self.dim());
+                reset.default_fg = self.default_fg;
+                reset.default_bg = self.default_bg;
+                std::mem::swap(&mut reset.parser, &mut self.parser);
+                *self = reset;
+            }
+            _ => {}
+        }
+    }
+
+    fn csi(&mut self, params: &[usize], action: u8) {
+        // Most sequences treat a missing or 0 parameter as 1
+        let n = |i: usize| match params.get(i) {
+            Some(&p) if p > 0 => p,
+            _ => 1,
+        };
+        let p0 = params.first().copied().unwrap_or(0);
+        self.pending_wrap = false;
+        match action {
+            b'A' => self.row = self.row.saturating_sub(n(0)).max(self.top_limit()),
+            b'B' => self.row = self.row.saturating_add(n(0)).min(self.bottom_limit()),
+            b'C' => {
+                self.col = self
+                    .col
+                    .saturating_add(n(0))
+                    .min(self.ncols.saturating_sub(1))
+            }
+            b'D' => self.col = self.col.saturating_sub(n(0)),
+            b'E' => {
+                self.row = self.row.saturating_add(n(0)).min(self.bottom_limit());
+                self.col = 0;
+            }
+            b'F' => {
+                self.row = self.row.saturating_sub(n(0)).max(self.top_limit());
+                self.col = 0;
+            }
+            b'G' => self.col = (n(0) - 1).min(self.ncols.saturating_sub(1)),
+            b'd' => self.row = (n(0) - 1).min(self.nrows.saturating_sub(1)),
+            b'H' | b'f' => {
+                self.row = (n(0) - 1).min(self.nrows.saturating_sub(1));
+                self.col = (n(1) - 1).min(self.ncols.saturating_sub(1));
+            }
+            b'J' => self.erase_display(p0),
+            b'K' => self.erase_line(p0),
+            b'L' => self.insert_lines(n(0)),
+            b'M' => self.delete_lines(n(0)),
+            b'@' => self.insert_chars(n(0)),
+            b'P' => self.delete_chars(n(0)),
+            b'S' => self.scroll_up(n(0)),
+            b'T' => self.scroll_down(n(0)),
+            b'm' => self.sgr(params),
+            b'r' => {
+                let top = n(0) - 1;
+                let bottom = match params.get(1) {
+                    Some(&p) if p > 0 => p - 1,
+                    _ => self.nrows.saturating_sub(1),
+                };
+                if top < bottom && bottom < self.nrows {
+                    self.scroll_top = top;
+                    self.scroll_bottom = bottom;
+                    self.row = 0;
+                    self.col = 0;
+                }
+            }
+            b's' => self.saved_cursor = (self.row, self.col),
+            b'u' => self.restore_cursor(),
+            _ => {}
+        }
+    }
+
+    fn sgr(&mut self, params: &[usize]) {
+        if params.is_empty() {
+            self.style = Style::DEFAULT;
+            return;
+        }
+        let mut i = 0;
+        while i < params.len() {
+            match params[i] {
+                0 => self.style = Style::DEFAULT,
+                1 => self.style.bold = true,
+                22 => self.style.bold = false,
+                7 => self.style.inverse = true,
+                27 => self.style.inverse = false,
+                p @ 30..=37 => self.set_fg_index((p - 30) as u8),
+                p @ 90..=97 => self.set_fg_index((p - 90 + 8) as u8),
+                39 => {
+                    self.style.fg = None;
+                    self.style.fg_index = None;
+                }
+                p @ 40..=47 => self.style.bg = Some(palette::indexed((p - 40) as u8)),
+                p @ 100..=107 => self.style.bg = Some(palette::indexed((p - 100 + 8) as u8)),
+                49 => self.style.bg = None,
+                p @ 38 | p @ 48 => {
+                    let (color, used) = extended_color(&params[i + 1..]);
+                    i += used;
+                    if let Some(color) = color {
+                        if p == 38 {
+                            self.style.fg = Some(color);
+                            self.style.fg_index = None;
+                        } else {
+                            self.style.bg = Some(color);
+                        }
+                    }
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+    }
+
+    fn set_fg_index(&mut self, index: u8) {
+        self.style.fg = Some(palette::indexed(index));
+        self.style.fg_index = Some(index);
+    }
+
+    fn restore_cursor(&mut self) {
+        let (row, col) = self.saved_cursor;
+        self.row = row.min(self.nrows.saturating_sub(1));
+        self.col = col.min(self.ncols.saturating_sub(1));
+        self.pending_wrap = false;
+    }
+
+    /// The cursor can only move up to the top of the scroll region
+    /// if it is already inside of it
+    fn top_limit(&self) -> usize {
+        if self.row >= self.scroll_top {
+            self.scroll_top
+        } else {
+            0
+        }
+    }
+
+    fn bottom_limit(&self) -> usize {
+        if self.row <= self.scroll_bottom {
+            self.scroll_bottom
+        } else {
+            self.nrows.saturating_sub(1)
+        }
+    }
+
+    fn line_feed(&mut self) {
+        self.pending_wrap = false;
+        if self.row == self.scroll_bottom {
+            self.scroll_up(1);
+        } else if self.row + 1 < self.nrows {
+            self.row += 1;
+        }
+    }
+
+    fn blank(&self) -> Cell {
+        // erased cells keep the current background color
+        Cell {
+            ch: ' ',
+            style: Style {
+                bg: self.style.bg,
+                ..Style::DEFAULT
+            },
+        }
+    }
+
+    fn set_cell(&mut self, row: usize, col: usize, cell: Cell) {
+        let i = row * self.ncols + col;
+        if self.cells[i] != cell {
+            self.cells[i] = cell;
+            self.dirty[i] = true;
+        }
+    }
+
+    fn mark_all_dirty(&mut self) {
+        for dirty in &mut self.dirty {
+            *dirty = true;
+        }
+    }
+
+    fn clear_range(&mut self, row: usize, cols: std::ops::Range<usize>) {
+        let blank = self.blank();
+        for col in cols {
+            self.set_cell(row, col, blank);
+        }
+    }
+
+    fn copy_row(&mut self, from: usize, to: usize) {
+        for col in 0..self.ncols {
+            let cell = self.cells[from * self.ncols + col];
+            self.set_cell(to, col, cell);
+        }
+    }
+
+    /// Moves the rows of [top, bottom] up by n, filling the
+    /// bottom with blank rows
+    fn shift_up(&mut self, top: usize, bottom: usize, n: usize) {
+        for row in top..=bottom {
+            if row.saturating_add(n) <= bottom {
+                self.copy_row(row + n, row);
+            } else {
+                self.clear_range(row, 0..self.ncols);
+            }
+        }
+    }
+
+    /// Moves the rows of [top, bottom] down by n, filling the
+    /// top with blank rows
+    fn shift_down(&mut self, top: usize, bottom: usize, n: usize) {
+        for row in (top..=bottom).rev() {
+            if row >= top.saturating_add(n) {
+                self.copy_row(row - n, row);
+            } else {
+                self.clear_range(row, 0..self.ncols);
+            }
+        }
+    }
+
+    fn scroll_up(&mut self, n: usize) {
+        self.shift_up(self.scroll_top, self.scroll_bottom, n);
+    }
+
+    fn scroll_down(&mut self, n: usize) {
+        self.shift_down(self.scroll_top, self.scroll_bottom, n);
+    }
+
+    fn insert_lines(&mut self, n: usize) {
+        if self.row >= self.scroll_top && self.row <= self.scroll_bottom {
+            self.shift_down(self.row, self.scroll_bottom, n);
+            self.col = 0;
+        }
+    }
+
+    fn delete_lines(&mut self, n: usize) {
+        if self.row >= self.scroll_top && self.row <= self.scroll_bottom {
+            self.shift_up(self.row, self.scroll_bottom, n);
+            self.col = 0;
+        }
+    }
+
+    fn insert_chars(&mut self, n: usize) {
+        let row = self.row;
+        for col in (self.col..self.ncols).rev() {
+            if col >= self.col.saturating_add(n) {
+                let cell = self.cells[row * self.ncols + col - n];
+                self.set_cell(row, col, cell);
+            } else {
+                let blank = self.blank();
+                self.set_cell(row, col, blank);
+            }
+        }
+    }
+
+    fn delete_chars(&mut self, n: usize) {
+        let row = self.row;
+        for col in self.col..self.ncols {
+            if col.saturating_add(n) < self.ncols {
+                let cell = self.cells[row * self.ncols + col + n];
+                self.set_cell(row, col, cell);
+            } else {
+                let blank = self.blank();
+                self.set_cell(row, col, blank);
+            }
+        }
+    }
+
+    fn erase_line(&mut self, mode: usize) {
+        let row = self.row;
+        match mode {
+            0 => self.clear_range(row, self.col..self.ncols),
+            1 => self.clear_range(row, 0..(self.col + 1).min(self.ncols)),
+            2 => self.clear_range(row, 0..self.ncols),
+            _ => {}
+        }
+    }
+
+    fn erase_display(&mut self, mode: usize) {
+        match mode {
+            0 => {
+                self.erase_line(0);
+                for row in self.row + 1..self.nrows {
+                    self.clear_range(row, 0..self.ncols);
+                }
+            }
+            1 => {
+                self.erase_line(1);
+                for row in 0..self.row {
+                    self.clear_range(row, 0..self.ncols);
+                }
+            }
+            2 | 3 => {
+                for row in 0..self.nrows {
+                    self.clear_range(row, 0..self.ncols);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Parses the parameters following an SGR 38 or 48
+/// (`5;n` for the 256 color palette or `2;r;g;b` for true color).
+/// Returns the color and the number of parameters consumed
+fn extended_color(params: &[usize]) -> (Option<Color>, usize) {
+    match params {
+        [5, n, ..] => (Some(palette::indexed((*n).min(255) as u8)), 2),
+        [2, r, g, b, ..] => {
+            let c = |x: usize| x.min(255) as u8;
+            (Some(palette::rgb(c(*r), c(*g), c(*b))), 4)
+        }
+        [5, ..] => (None, params.len()),
+        [2, ..] => (None, params.len()),
+        _ => (None, 0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn terminal(nrows: usize, ncols: usize) -> Terminal {
+        Terminal::new(TextGridDim { nrows, ncols })
+    }
+
+    fn row_text(terminal: &Terminal, row: usize) -> String {
+        (0..terminal.ncols)
+            .map(|col| terminal.get_char(row, col).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn print_and_newline() {
+        let mut term = terminal(3, 5);
+        term.feed(b"ab\r\ncd");
+        assert_eq!(row_text(&term, 0), "ab   ");
+        assert_eq!(row_text(&term, 1), "cd   ");
+        assert_eq!(term.cursor(), [1, 2]);
+    }
+
+    #[test]
+    fn get_char_out_of_bounds() {
+        let term = terminal(2, 3);
+        assert_eq!(term.get_char(1, 2), Some(' '));
+        assert_eq!(term.get_char(2, 0), None);
+        assert_eq!(term.get_char(0, 3), None);
+    }
+
+    #[test]
+    fn wrapping() {
+        let mut term = terminal(2, 3);
+        term.feed(b"abc");
+        // The cursor stays in the last column until the next character
+        assert_eq!(term.cursor(), [0, 2]);
+        term.feed(b"d");
+        assert_eq!(row_text(&term, 0), "abc");
+        assert_eq!(row_text(&term, 1), "d  ");
+        assert_eq!(term.cursor(), [1, 1]);
+    }
+
+    #[test]
+    fn scrolling_at_the_bottom() {
+        let mut term = terminal(2, 3);
+        term.feed(b"a\r\nb\r\nc");
+        assert_eq!(row_text(&term, 0), "b  ");
+        assert_eq!(row_text(&term, 1), "c  ");
+    }
+
+    #[test]
+    fn cursor_movement() {
+        let mut term = terminal(5, 10);
+        term.feed(b"\x1b[3;4H");
+        assert_eq!(term.cursor(), [2, 3]);
+        term.feed(b"\x1b[2A");
+        assert_eq!(term.cursor(), [0, 3]);
+        term.feed(b"\x1b[B\x1b[3C");
+        assert_eq!(term.cursor(), [1, 6]);
+        term.feed(b"\x1b[10D");
+        assert_eq!(term.cursor(), [1, 0]);
+        term.feed(b"\x1b[100;100H");
+        assert_eq!(term.cursor(), [4, 9]);
+        term.feed(b"\x1b[H\x1b[2E");
+        assert_eq!(term.cursor(), [2, 0]);
+        term.feed(b"\x1b[5G\x1b[F");
+        assert_eq!(term.cursor(), [1, 0]);
+    }
+
+    #[test]
+    fn huge_counts_do_not_overflow() {
+        let mut term = terminal(4, 6);
+        term.feed(b"abc\x1b[2;2H");
+        for action in b"BCE@PLMST" {
+            term.feed(b"\x1b[99999999999999999999999");
+            term.feed(&[*action]);
+        }
+        term.feed(b"\x1b[99999999999999999999999;99999999999999999999999r");
+        assert!(term.cursor()[0] < 4 && term.cursor()[1] < 6);
+        term.feed(b"\x1b[2;3r\x1b[99999999999999999999999S");
+        term.feed(b"\x1b[99999999999999999999999T");
+    }
+
+    #[test]
+    fn empty_grid() {
+        let mut term = terminal(0, 0);
+        term.feed(b"abc\n\r\x1b[5B\x1b[2J\x1bM\x1b[L\x1bc");
+        assert_eq!(term.get_char(0, 0), None);
+        let mut term = terminal(0, 5);
+        term.feed(b"\n\x1b[S");
+        let mut term = terminal(3, 0);
+        term.feed(b"x\x1b[@\x1b[P");
+    }
+
+    #[test]
+    fn scroll_region() {
+        let mut term = terminal(4, 1);
+        term.feed(b"a\r\nb\r\nc\r\nd");
+        // Rows 2 and 3 (1-based) scroll, the others stay
+        term.feed(b"\x1b[2;3r\x1b[3;1H\n");
+        assert_eq!(row_text(&term, 0), "a");
+        assert_eq!(row_text(&term, 1), "c");
+        assert_eq!(row_text(&term, 2), " ");
+        assert_eq!(row_text(&term, 3), "d");
+        term.feed(b"\x1b[2;1H\x1bM");
+        assert_eq!(row_text(&term, 1), " ");
+        assert_eq!(row_text(&term, 2), "c");
+        assert_eq!(row_text(&term, 3), "d");
+    }
+
+    #[test]
+    fn insert_and_delete_chars() {
+        let mut term = terminal(1, 5);
+        term.feed(b"abcde\x1b[1;2H\x1b[2@");
+        assert_eq!(row_text(&term, 0), "a  bc");
+        term.feed(b"\x1b[3P");
+        assert_eq!(row_text(&term, 0), "ac   ");
+    }
+
+    #[test]
+    fn erase() {
+        let mut term = terminal(2, 4);
+        term.feed(b"abcd\r\nefgh\x1b[1;3H\x1b[K");
+        assert_eq!(row_text(&term, 0), "ab  ");
+        term.feed(b"\x1b[1K");
+        assert_eq!(row_text(&term, 0), "    ");
+        term.feed(b"\x1b[2J");
+        assert_eq!(row_text(&term, 1), "    ");
+    }
+
+    #[test]
+    fn sgr() {
+        let mut term = terminal(1, 8);
+        term.feed(b"\x1b[1;31ma\x1b[22;44mb\x1b[38;2;1;2;3mc\x1b[7md\x1b[0me");
+        let style = |col: usize| term.cells[col].style;
+        assert!(style(0).bold);
+        assert_eq!(style(0).fg_index, Some(1));
+        assert_eq!(style(0).fg, Some(palette::indexed(1)));
+        assert!(!style(1).bold);
+        assert_eq!(style(1).bg, Some(palette::indexed(4)));
+        assert_eq!(style(2).fg, Some(palette::rgb(1, 2, 3)));
+        assert_eq!(style(2).fg_index, None);
+        assert!(style(3).inverse);
+        assert_eq!(style(4), Style::DEFAULT);
+        // Bold brightens the first 8 palette colors
+        assert_eq!(term.resolve_colors(style(0)).0, palette::indexed(9));
+    }
+
+    #[test]
+    fn split_utf8_and_escapes() {
+        let mut term = terminal(1, 4);
+        let bytes = "é\x1b[1;3H€".as_bytes();
+        for chunk in bytes.chunks(1) {
+            term.feed(chunk);
+        }
+        assert_eq!(row_text(&term, 0), "é € ");
+        term.feed(b"\x1b[1;1H\xe2\x82\x1b[1;4Hx");
+        assert_eq!(row_text(&term, 0), "\u{fffd} €x");
+    }
+}
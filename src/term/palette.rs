@@ -0,0 +1,46 @@
+use crate::Color;
+
+/// The 16 standard ANSI colors (normal then bright), xterm flavored
+const ANSI_COLORS: [[u8; 3]; 16] = [
+    [0, 0, 0],
+    [205, 0, 0],
+    [0, 205, 0],
+    [205, 205, 0],
+    [0, 0, 238],
+    [205, 0, 205],
+    [0, 205, 205],
+    [229, 229, 229],
+    [127, 127, 127],
+    [255, 0, 0],
+    [0, 255, 0],
+    [255, 255, 0],
+    [92, 92, 255],
+    [255, 0, 255],
+    [0, 255, 255],
+    [255, 255, 255],
+];
+
+pub(super) fn rgb(r: u8, g: u8, b: u8) -> Color {
+    [r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0].into()
+}
+
+/// Looks up a color in the xterm 256 color palette
+pub(super) fn indexed(i: u8) -> Color {
+    match i {
+        0..=15 => {
+            let [r, g, b] = ANSI_COLORS[i as usize];
+            rgb(r, g, b)
+        }
+        16..=231 => {
+            // 6x6x6 color cube
+            let i = i - 16;
+            let level = |x: u8| if x == 0 { 0 } else { 55 + 40 * x };
+            rgb(level(i / 36), level((i / 6) % 6), level(i % 6))
+        }
+        232..=255 => {
+            // grayscale ramp
+            let v = 8 + 10 * (i - 232);
+            rgb(v, v, v)
+        }
+    }
+}
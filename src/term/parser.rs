@@ -0,0 +1,291 @@
+/// Something the terminal should do, as decoded from the byte stream
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum Action {
+    /// A printable character
+    Print(char),
+
+    /// A C0 control character such as '\n' or '\r'
+    Control(u8),
+
+    /// An escape sequence of the form `ESC <byte>`
+    Esc(u8),
+
+    /// A control sequence of the form `ESC [ <params> <final>`
+    /// Missing parameters are reported as 0, and sequences with
+    /// sub-parameters (separated by ':') aren't reported at all
+    Csi {
+        params: Vec<usize>,
+        private: bool,
+        action: u8,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    Ground,
+    Escape,
+    Csi,
+    Osc,
+    OscEscape,
+}
+
+/// Incremental decoder for a byte stream containing UTF-8 text
+/// and ANSI escape sequences.
+///
+/// Sequences may be split across calls to `advance`.
+pub(super) struct Parser {
+    state: State,
+    params: Vec<usize>,
+    current_param: Option<usize>,
+    private: bool,
+    /// Set for sequences that are consumed without being reported
+    ignore: bool,
+    utf8: Vec<u8>,
+}
+
+impl Parser {
+    pub fn new() -> Self {
+        Self {
+            state: State::Ground,
+            params: vec![],
+            current_param: None,
+            private: false,
+            ignore: false,
+            utf8: vec![],
+        }
+    }
+
+    pub fn advance(&mut self, byte: u8, out: &mut Vec<Action>) {
+        match self.state {
+            State::Ground => self.ground(byte, out),
+            State::Escape => match byte {
+                b'[' => {
+                    self.params.clear();
+                    self.current_param = None;
+                    self.private = false;
+                    self.ignore = false;
+                    self.state = State::Csi;
+                }
+                b']' => self.state = State::Osc,
+                _ => {
+                    out.push(Action::Esc(byte));
+                    self.state = State::Ground;
+                }
+            },
+            State::Csi => match byte {
+                b'0'..=b'9' => {
+                    let digit = (byte - b'0') as usize;
+                    let param = self.current_param.unwrap_or(0);
+                    self.current_param = Some(param.saturating_mul(10).saturating_add(digit));
+                }
+                b';' => {
+                    self.params.push(self.current_param.take().unwrap_or(0));
+                }
+                b'<' | b'=' | b'>' | b'?' => self.private = true,
+                // sub-parameters (as in `ESC [ 38:2:r:g:b m`) aren't supported
+                b':' => self.ignore = true,
+                // intermediate bytes; none of the sequences we support use them
+                0x20..=0x2f => {}
+                0x40..=0x7e => {
+                    if let Some(param) = self.current_param.take() {
+                        self.params.push(param);
+                    }
+                    let params = std::mem::take(&mut self.params);
+                    if !self.ignore {
+                        out.push(Action::Csi {
+                            params,
+                            private: self.private,
+                            action: byte,
+                        });
+                    }
+                    self.state = State::Ground;
+                }
+                0x1b => self.state = State::Escape,
+                // control characters are still executed in the middle of a sequence
+                0x00..=0x1f => out.push(Action::Control(byte)),
+                _ => self.state = State::Ground,
+            },
+            // Operating system commands (e.g. setting the window title)
+            // are consumed and ignored
+            State::Osc => match byte {
+                0x07 => self.state = State::Ground,
+                0x1b => self.state = State::OscEscape,
+                _ => {}
+            },
+            State::OscEscape => {
+                self.state = if byte == b'\\' {
+                    State::Ground
+                } else {
+                    State::Osc
+                };
+            }
+        }
+    }
+
+    fn ground(&mut self, byte: u8, out: &mut Vec<Action>) {
+        let continuation = byte & 0xc0 == 0x80;
+        if !self.utf8.is_empty() && !continuation {
+            // The character was cut short; only the bytes received so far
+            // are replaced, and this byte starts over on its own
+            out.push(Action::Print(std::char::REPLACEMENT_CHARACTER));
+            self.utf8.clear();
+        }
+        if !self.utf8.is_empty() || byte >= 0x80 {
+            self.utf8.push(byte);
+            match std::str::from_utf8(&self.utf8) {
+                Ok(s) => {
+                    out.extend(s.chars().map(Action::Print));
+                    self.utf8.clear();
+                }
+                Err(e) if e.error_len().is_some() => {
+                    out.push(Action::Print(std::char::REPLACEMENT_CHARACTER));
+                    self.utf8.clear();
+                }
+                // the character is incomplete, wait for more bytes
+                Err(_) => {}
+            }
+            return;
+        }
+        match byte {
+            0x1b => self.state = State::Escape,
+            0x00..=0x1f | 0x7f => out.push(Action::Control(byte)),
+            _ => out.push(Action::Print(byte as char)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(bytes: &[u8]) -> Vec<Action> {
+        let mut parser = Parser::new();
+        let mut out = vec![];
+        for &byte in bytes {
+            parser.advance(byte, &mut out);
+        }
+        out
+    }
+
+    #[test]
+    fn text_and_controls() {
+        assert_eq!(
+            parse(b"a\r\n"),
+            vec![
+                Action::Print('a'),
+                Action::Control(b'\r'),
+                Action::Control(b'\n'),
+            ]
+        );
+    }
+
+    #[test]
+    fn csi_params() {
+        assert_eq!(
+            parse(b"\x1b[1;;42m\x1b[H\x1b[?25l"),
+            vec![
+                Action::Csi {
+                    params: vec![1, 0, 42],
+                    private: false,
+                    action: b'm',
+                },
+                Action::Csi {
+                    params: vec![],
+                    private: false,
+                    action: b'H',
+                },
+                Action::Csi {
+                    params: vec![25],
+                    private: true,
+                    action: b'l',
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn csi_with_unsupported_bytes_is_consumed() {
+        assert_eq!(
+            parse(b"\x1b[<0;3;4Mx"),
+            vec![
+                Action::Csi {
+                    params: vec![0, 3, 4],
+                    private: true,
+                    action: b'M',
+                },
+                Action::Print('x'),
+            ]
+        );
+        assert_eq!(parse(b"\x1b[38:2:255:0:0mx"), vec![Action::Print('x')]);
+        assert_eq!(
+            parse(b"\x1b[4:3m\x1b[m"),
+            vec![Action::Csi {
+                params: vec![],
+                private: false,
+                action: b'm',
+            }]
+        );
+    }
+
+    #[test]
+    fn huge_params_saturate() {
+        assert_eq!(
+            parse(b"\x1b[99999999999999999999999B"),
+            vec![Action::Csi {
+                params: vec![usize::MAX],
+                private: false,
+                action: b'B',
+            }]
+        );
+    }
+
+    #[test]
+    fn osc_is_ignored() {
+        assert_eq!(parse(b"\x1b]0;title\x07x"), vec![Action::Print('x')]);
+        assert_eq!(parse(b"\x1b]0;title\x1b\\x"), vec![Action::Print('x')]);
+    }
+
+    #[test]
+    fn utf8() {
+        assert_eq!(
+            parse("é€😀".as_bytes()),
+            vec![Action::Print('é'), Action::Print('€'), Action::Print('😀'),]
+        );
+        assert_eq!(parse(b"\xff"), vec![Action::Print('\u{fffd}')]);
+    }
+
+    #[test]
+    fn utf8_split_across_calls() {
+        let mut parser = Parser::new();
+        let mut out = vec![];
+        let bytes = "€".as_bytes();
+        parser.advance(bytes[0], &mut out);
+        parser.advance(bytes[1], &mut out);
+        assert_eq!(out, vec![]);
+        parser.advance(bytes[2], &mut out);
+        assert_eq!(out, vec![Action::Print('€')]);
+    }
+
+    #[test]
+    fn truncated_utf8_keeps_the_next_byte() {
+        assert_eq!(
+            parse(b"\xe2\x82\x1b[m"),
+            vec![
+                Action::Print('\u{fffd}'),
+                Action::Csi {
+                    params: vec![],
+                    private: false,
+                    action: b'm',
+                },
+            ]
+        );
+        assert_eq!(
+            parse(b"\xe2a"),
+            vec![Action::Print('\u{fffd}'), Action::Print('a')]
+        );
+        assert_eq!(
+            parse(b"\xe2\xc3\xa9"),
+            vec![Action::Print('\u{fffd}'), Action::Print('é')]
+        );
+    }
+}